@@ -6,7 +6,7 @@ use std::path::Path;
 
 include!("src/app.rs");
 
-const APP_NAME: &'static str = "gmux";
+const APP_NAME: &str = "gmux";
 
 fn main() -> Result<(), Error> {
     // HACK: It's very much a hack to expose completion files as artifacts.
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{AppSettings, ArgEnum, Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Debug, Subcommand)]
@@ -28,9 +28,184 @@ pub enum PoolCommands {
     /// Add an exclusion rule for this pool discovery.
     Exclude { pattern: String },
     /// Clone missing repositories of the current pool, checkout appropriate branches.
-    Checkout,
+    Checkout {
+        /// Number of repositories to clone in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Stash uncommitted changes before switching branches, and restore them afterwards.
+        #[clap(long)]
+        autostash: bool,
+        #[clap(flatten)]
+        selection: SelectionArgs,
+    },
     /// Save managed repositories current state.
     Discover,
+    /// Tag a repository of the current pool, to select it with `--tag`.
+    Tag {
+        /// Repository path relative to the pool root, or its name if unique.
+        repository: String,
+        tag: String,
+        /// Remove the tag instead of adding it.
+        #[clap(short, long)]
+        remove: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BranchCommands {
+    /// Create a branch where it does not exist yet.
+    Create {
+        name: String,
+        /// Create the branch from this reference instead of HEAD.
+        #[clap(long)]
+        from: Option<String>,
+        /// Push the branch to the fetch remote, and track it.
+        #[clap(long)]
+        push: bool,
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to update in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Switch to a branch, created from the fetch remote if needed.
+    Switch {
+        name: String,
+        /// Stash uncommitted changes before switching branches, and restore them afterwards.
+        #[clap(long)]
+        autostash: bool,
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to update in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Delete a merged branch.
+    Delete {
+        name: String,
+        /// Delete the branch even if it is not merged.
+        #[clap(long)]
+        force: bool,
+        /// Delete the branch from the fetch remote too.
+        #[clap(long)]
+        push: bool,
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to update in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, ArgEnum)]
+pub enum Order {
+    /// Sort outputs by repository path.
+    Path,
+    /// Print outputs as soon as commands complete.
+    Completion,
+}
+
+#[derive(Debug, Clone, ArgEnum)]
+pub enum Color {
+    /// Color output on a terminal, unless `NO_COLOR` is set.
+    Auto,
+    /// Always color output.
+    Always,
+    /// Never color output.
+    Never,
+}
+
+#[derive(Debug, Clone, ArgEnum)]
+pub enum FilterOn {
+    /// Filter both the standard output and error.
+    Both,
+    /// Only filter the standard output.
+    Stdout,
+    /// Only filter the standard error.
+    Stderr,
+}
+
+#[derive(Debug, Clone, ArgEnum)]
+pub enum Format {
+    /// A header per repository, followed by its output.
+    Text,
+    /// A single JSON array of records.
+    Json,
+    /// A JSON record per line.
+    Ndjson,
+}
+
+#[derive(Debug, Args)]
+/// Options selecting the repositories a command applies to.
+pub struct SelectionArgs {
+    /// Only select repositories with this tag, can be repeated.
+    #[clap(long, multiple_occurrences = true)]
+    pub tag: Vec<String>,
+    /// Do not select repositories with this tag, can be repeated.
+    #[clap(long, multiple_occurrences = true)]
+    pub exclude_tag: Vec<String>,
+    /// Only select repositories matching this expression.
+    ///
+    /// Compare `name`, `path`, `branch` and `remote` (fetch URL) from the pool,
+    /// or `head` (current branch), `ahead` and `behind` from the working copy
+    /// using `==`, `!=`, `<`, `<=`, `>`, `>=`, or `~` and `!~` for regular
+    /// expressions. `dirty`, `detached` and `has_stash` are true or false.
+    /// Combine them with `and`, `or`, `not` and parentheses, for instance
+    /// `head != main and dirty`.
+    #[clap(short, long = "where", value_name = "EXPRESSION")]
+    pub where_: Option<String>,
+}
+
+#[derive(Debug, Args)]
+/// Options shared by commands running on every repository.
+pub struct CallArgs {
+    #[clap(flatten)]
+    pub selection: SelectionArgs,
+    /// Do not print outputs matching this pattern, can be repeated.
+    #[clap(short, long, multiple_occurrences = true)]
+    pub exclude_filter: Vec<String>,
+    /// Only print outputs matching this pattern, can be repeated.
+    #[clap(short, long, multiple_occurrences = true)]
+    pub filter: Vec<String>,
+    /// Output streams the filters apply to, other streams are printed as is.
+    #[clap(long, arg_enum, default_value = "both")]
+    pub filter_on: FilterOn,
+    /// Match filters against each line, and only print matching lines.
+    #[clap(long)]
+    pub grep_lines: bool,
+    /// Order of the repositories output.
+    ///
+    /// Defaults to `completion` when printing to a terminal, `path` otherwise.
+    #[clap(long, arg_enum)]
+    pub order: Option<Order>,
+    /// Format of the repositories output.
+    #[clap(long, arg_enum, default_value = "text")]
+    pub format: Format,
+    /// Stop running the command on new repositories after the first failure.
+    #[clap(long)]
+    pub fail_fast: bool,
+    /// Number of repositories to run the command on in parallel.
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+    /// Number of repositories sharing a remote host to run the command on in parallel.
+    #[clap(long)]
+    pub host_jobs: Option<usize>,
+    /// Run the command on one repository at a time, attached to the terminal.
+    ///
+    /// This allows commands needing an editor or user input. You are prompted
+    /// before each repository to continue, skip it, or abort. Filters and
+    /// output formats do not apply in this mode.
+    #[clap(short, long)]
+    pub interactive: bool,
+    /// Print each distinct output once, followed by the repositories producing it.
+    ///
+    /// Outputs are printed once every command completed, largest groups first.
+    /// Only applies to the text format.
+    #[clap(short, long)]
+    pub group: bool,
+    /// Kill the command of a repository running longer than this number of seconds.
+    #[clap(short, long)]
+    pub timeout: Option<f64>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -50,20 +225,112 @@ pub enum ApplicationCommands {
         pool_command: Option<PoolCommands>,
     },
     /// Run any git command on every repository of the current pool.
+    ///
+    /// Arguments may contain the `{name}`, `{path}`, `{full_path}`, `{branch}`,
+    /// `{fetch_remote}`, `{fetch_url}`, `{pool}` and `{pool_root}` placeholders,
+    /// replaced by each repository values. The same values are exported to the
+    /// command environment as `GMUX_REPO_*` and `GMUX_POOL_*` variables.
     Command {
-        #[clap(short, long)]
-        exclude_filter: Option<String>,
-        #[clap(short, long)]
-        filter: Option<String>,
+        #[clap(flatten)]
+        call: CallArgs,
+        /// Run the command on the repositories of every pool.
+        #[clap(long)]
+        all_pools: bool,
         #[clap(subcommand)]
         command: GitCommand,
     },
+    /// Create, switch or delete a branch across the repositories of the current pool.
+    Branch {
+        #[clap(subcommand)]
+        branch_command: BranchCommands,
+    },
+    /// Show where a topic branch exists across the current pool.
+    ///
+    /// Each repository row tells whether the branch exists locally and on
+    /// the fetch remote, how it compares to the default branch, whether it
+    /// is merged, and the age of its last commit.
+    Topic {
+        name: String,
+        /// Delete the local and remote branches which are merged in the default branch.
+        #[clap(long)]
+        cleanup: bool,
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to read in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Print topics as a JSON array.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show the state of every repository of the current pool.
+    ///
+    /// Compare the current branch with the one recorded in the pool,
+    /// and count commits ahead and behind upstream, staged, unstaged
+    /// and untracked files, and stashes.
+    Status {
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to read in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Print statuses as a JSON array.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Fetch every remote of every repository of the current pool.
+    Fetch {
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to fetch in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Keep remote branches deleted from their remote.
+        #[clap(long)]
+        no_prune: bool,
+    },
+    /// Fast-forward the current branch of every repository of the current pool.
+    ///
+    /// The upstream of each branch is fetched first. Repositories with
+    /// uncommitted changes, a detached HEAD, no upstream or a branch which
+    /// diverged from its upstream are skipped.
+    Sync {
+        #[clap(flatten)]
+        selection: SelectionArgs,
+        /// Number of repositories to synchronize in parallel.
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Rebase clean branches which diverged from their upstream, aborting on conflicts.
+        #[clap(long)]
+        rebase: bool,
+    },
+    /// Run any program from every repository of the current pool.
+    ///
+    /// Supports the same placeholders and environment variables as `gmux command`.
+    #[clap(setting = AppSettings::TrailingVarArg)]
+    Exec {
+        #[clap(flatten)]
+        call: CallArgs,
+        /// Run the command line through the system shell, placeholders being
+        /// quoted so they must not be quoted again.
+        #[clap(long)]
+        shell: bool,
+        /// Program to run, followed by its arguments.
+        #[clap(required = true)]
+        command: Vec<String>,
+    },
 }
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, name = "gmux")]
 /// Manage multiple git repositories with ease.
 pub struct Application {
+    /// When to color output, git commands output included unless it is filtered or grouped.
+    #[clap(long, arg_enum, global = true, default_value = "auto")]
+    pub color: Color,
+    /// Pool to work on instead of the current one, for this invocation only.
+    #[clap(long, global = true)]
+    pub pool: Option<String>,
     #[clap(subcommand)]
     pub command: ApplicationCommands,
 }
@@ -0,0 +1,416 @@
+use super::{
+    BranchAction, BranchCheckout, CheckoutManager, CredentialsProvider, Error, FetchManager,
+    HostThrottle, Pool, Repository, RepositoryState, Result, Selection, Settings,
+    LOCAL_BRANCH_PREFIX,
+};
+use git2::{Branch, BranchType, Direction, Oid, PushOptions};
+use rayon::prelude::*;
+use std::fmt;
+
+pub struct BranchManager;
+
+impl BranchManager {
+    /// Create a branch in the selected repositories where it does not exist,
+    /// from `from` or HEAD, optionally pushing it to the fetch remote.
+    pub fn create(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        name: &str,
+        from: Option<&str>,
+        push: bool,
+    ) -> Result<Vec<BranchChange>> {
+        BranchManager::run(settings, jobs, selection, |pool, repository, throttle| {
+            let git = git2::Repository::open(repository.full_path(&pool.root))?;
+
+            let (mut branch, created) = match git.find_branch(name, BranchType::Local) {
+                Ok(branch) => (branch, false),
+                Err(_) => {
+                    let commit = match from {
+                        Some(from) => git.revparse_single(from)?.peel_to_commit()?,
+                        None => git.head()?.peel_to_commit()?,
+                    };
+                    (git.branch(name, &commit, false)?, true)
+                }
+            };
+
+            // Existing branches are only pushed when they do not track a remote yet.
+            let pushed = push && branch.upstream().is_err();
+            if pushed {
+                let refname = format!("{}{}", LOCAL_BRANCH_PREFIX, name);
+                let _permit = throttle.acquire(repository.fetch.host());
+                BranchManager::push(
+                    settings,
+                    &git,
+                    &repository.fetch.name,
+                    &format!("{}:{}", refname, refname),
+                )?;
+                branch.set_upstream(Some(&format!("{}/{}", repository.fetch.name, name)))?;
+            }
+
+            if created {
+                Ok(BranchOutcome::Created { pushed })
+            } else {
+                Ok(BranchOutcome::Existing { pushed })
+            }
+        })
+    }
+
+    /// Switch the selected repositories to a branch, created from the fetch
+    /// remote if it only exists there.
+    pub fn switch(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        name: &str,
+        autostash: bool,
+    ) -> Result<Vec<BranchChange>> {
+        BranchManager::run(settings, jobs, selection, |pool, repository, _| {
+            let git = git2::Repository::open(repository.full_path(&pool.root))?;
+
+            let current = String::from(git.head()?.name().unwrap_or_default());
+            let next = format!("{}{}", LOCAL_BRANCH_PREFIX, name);
+            if current == next {
+                return Ok(BranchOutcome::Current);
+            }
+
+            let upstream_name = format!("{}/{}", repository.fetch.name, name);
+            if git.find_branch(name, BranchType::Local).is_err()
+                && git.find_branch(&upstream_name, BranchType::Remote).is_err()
+            {
+                return Ok(BranchOutcome::Missing);
+            }
+            if !autostash && RepositoryState::is_dirty(&git)? {
+                return Ok(BranchOutcome::Skipped(String::from(
+                    "uncommitted changes, use --autostash",
+                )));
+            }
+
+            let mut branch = BranchCheckout {
+                repository: repository.clone(),
+                current,
+                next,
+                actions: Vec::new(),
+                error: None,
+            };
+            CheckoutManager::switch_branch(pool, &mut branch, autostash)?;
+
+            Ok(BranchOutcome::Switched {
+                actions: branch.actions,
+            })
+        })
+    }
+
+    /// Delete a branch from the selected repositories, and from their fetch
+    /// remote with `push`.
+    ///
+    /// Branches not merged in HEAD or their upstream, and remote branches not
+    /// merged in HEAD or its upstream, are kept unless `force` is set. The fetch
+    /// remote is fetched first, and its branch is only deleted if it did not
+    /// move since it was checked.
+    pub fn delete(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        name: &str,
+        force: bool,
+        push: bool,
+    ) -> Result<Vec<BranchChange>> {
+        BranchManager::run(settings, jobs, selection, |pool, repository, throttle| {
+            let git = git2::Repository::open(repository.full_path(&pool.root))?;
+            BranchManager::delete_branch(settings, &git, repository, throttle, name, force, push)
+        })
+    }
+
+    fn delete_branch(
+        settings: &Settings,
+        git: &git2::Repository,
+        repository: &Repository,
+        throttle: &HostThrottle,
+        name: &str,
+        force: bool,
+        push: bool,
+    ) -> Result<BranchOutcome> {
+        let mut branch = match git.find_branch(name, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(_) => return Ok(BranchOutcome::Missing),
+        };
+        if branch.is_head() {
+            return Ok(BranchOutcome::Skipped(String::from(
+                "it is the current branch",
+            )));
+        }
+
+        // Remote tracking branches may be stale, the remote branch is
+        // checked at its current tip.
+        if push {
+            BranchManager::fetch(settings, git, repository, throttle)?;
+        }
+        let remote = if push {
+            git.find_branch(
+                &format!("{}/{}", repository.fetch.name, name),
+                BranchType::Remote,
+            )
+            .ok()
+            .and_then(|remote| remote.get().target())
+        } else {
+            None
+        };
+
+        if !force {
+            let merged_in = |tip: Option<Oid>, bases: &[Option<Oid>]| match tip {
+                Some(tip) => bases
+                    .iter()
+                    .flatten()
+                    .any(|base| BranchManager::is_merged(git, tip, *base)),
+                None => false,
+            };
+            let head = git.head()?;
+            let head_target = head.target();
+            let head_upstream = Branch::wrap(head)
+                .upstream()
+                .ok()
+                .and_then(|upstream| upstream.get().target());
+            let upstream = branch
+                .upstream()
+                .ok()
+                .and_then(|upstream| upstream.get().target());
+
+            if !merged_in(branch.get().target(), &[head_target, upstream]) {
+                return Ok(BranchOutcome::Skipped(String::from(
+                    "not fully merged, use --force",
+                )));
+            }
+            if remote.is_some() && !merged_in(remote, &[head_target, head_upstream]) {
+                return Ok(BranchOutcome::Skipped(String::from(
+                    "the remote branch is not fully merged, use --force",
+                )));
+            }
+        }
+
+        branch.delete()?;
+
+        let remote = match remote {
+            Some(remote) => remote,
+            None => return Ok(BranchOutcome::Deleted { pushed: false }),
+        };
+        let kept = |reason: String| BranchOutcome::PartlyDeleted {
+            pushed: false,
+            reason,
+        };
+        match BranchManager::remote_tip(settings, git, repository, throttle, name) {
+            Ok(Some(tip)) if tip == remote => (),
+            Ok(_) => {
+                return Ok(kept(String::from(
+                    "the remote branch moved since it was checked",
+                )))
+            }
+            Err(err) => return Ok(kept(format!("could not read the remote branch: {}", err))),
+        }
+        if let Err(err) = BranchManager::delete_remote(settings, git, repository, throttle, name) {
+            return Ok(kept(format!("could not delete the remote branch: {}", err)));
+        }
+
+        Ok(BranchOutcome::Deleted { pushed: true })
+    }
+
+    /// Fetch the fetch remote, pruning its deleted branches.
+    pub(crate) fn fetch(
+        settings: &Settings,
+        git: &git2::Repository,
+        repository: &Repository,
+        throttle: &HostThrottle,
+    ) -> Result<()> {
+        let _permit = throttle.acquire(repository.fetch.host());
+        FetchManager::fetch_remote(settings, git, &repository.fetch.name, true)?;
+        Ok(())
+    }
+
+    /// Current tip of a branch on the fetch remote, `None` if it does not exist.
+    pub(crate) fn remote_tip(
+        settings: &Settings,
+        git: &git2::Repository,
+        repository: &Repository,
+        throttle: &HostThrottle,
+        name: &str,
+    ) -> Result<Option<Oid>> {
+        let _permit = throttle.acquire(repository.fetch.host());
+        let refname = format!("{}{}", LOCAL_BRANCH_PREFIX, name);
+
+        let mut credentials = CredentialsProvider::new(settings);
+        let mut remote = git.find_remote(&repository.fetch.name)?;
+        let connection =
+            remote.connect_auth(Direction::Fetch, Some(credentials.callbacks()), None)?;
+        let tip = connection
+            .list()?
+            .iter()
+            .find(|head| head.name() == refname)
+            .map(|head| head.oid());
+
+        Ok(tip)
+    }
+
+    /// Delete a branch from the fetch remote.
+    pub(crate) fn delete_remote(
+        settings: &Settings,
+        git: &git2::Repository,
+        repository: &Repository,
+        throttle: &HostThrottle,
+        name: &str,
+    ) -> Result<()> {
+        let _permit = throttle.acquire(repository.fetch.host());
+        BranchManager::push(
+            settings,
+            git,
+            &repository.fetch.name,
+            &format!(":{}{}", LOCAL_BRANCH_PREFIX, name),
+        )
+    }
+
+    /// Whether `tip` is part of the history of `base`.
+    pub(crate) fn is_merged(git: &git2::Repository, tip: Oid, base: Oid) -> bool {
+        base == tip || git.graph_descendant_of(base, tip).unwrap_or(false)
+    }
+
+    /// Apply `action` to the selected repositories in parallel, failures
+    /// only affecting their own repository.
+    pub(crate) fn run<F>(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        action: F,
+    ) -> Result<Vec<BranchChange>>
+    where
+        F: Fn(&Pool, &Repository, &HostThrottle) -> Result<BranchOutcome> + Sync,
+    {
+        let pool = Pool::from_current(settings)?;
+
+        let (workers, throttle) = pool.workers(settings, jobs)?;
+
+        let mut changes: Vec<BranchChange> = workers.install(|| {
+            pool.repositories
+                .par_iter()
+                .filter(|repository| selection.matches(&pool, repository))
+                .map(|repository| {
+                    let outcome = if repository.full_path(&pool.root).is_dir() {
+                        action(&pool, repository, &throttle)
+                            .unwrap_or_else(|err| BranchOutcome::Failed(err.to_string()))
+                    } else {
+                        BranchOutcome::Skipped(String::from("the repository is missing"))
+                    };
+                    BranchChange {
+                        repository: repository.to_string(),
+                        outcome,
+                    }
+                })
+                .collect()
+        });
+        changes.sort_by(|a, b| a.repository.cmp(&b.repository));
+
+        Ok(changes)
+    }
+
+    pub(crate) fn push(
+        settings: &Settings,
+        git: &git2::Repository,
+        remote: &str,
+        refspec: &str,
+    ) -> Result<()> {
+        let mut credentials = CredentialsProvider::new(settings);
+        let mut rejection = None;
+        {
+            let mut callbacks = credentials.callbacks();
+            callbacks.push_update_reference(|_, status| {
+                rejection = status.map(String::from);
+                Ok(())
+            });
+            let mut po = PushOptions::new();
+            po.remote_callbacks(callbacks);
+            git.find_remote(remote)?.push(&[refspec], Some(&mut po))?;
+        }
+
+        match rejection {
+            Some(reason) => Err(Error::PushRejected {
+                refspec: String::from(refspec),
+                reason,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Result of a branch command on a repository.
+pub struct BranchChange {
+    /// Repository path, relative to the pool root.
+    pub repository: String,
+    pub outcome: BranchOutcome,
+}
+
+#[derive(Debug, PartialEq)]
+/// What a branch command did to a repository.
+pub enum BranchOutcome {
+    /// The branch was created, then pushed if requested.
+    Created {
+        pushed: bool,
+    },
+    /// The branch already existed, it was pushed if requested and not tracking a remote yet.
+    Existing {
+        pushed: bool,
+    },
+    /// HEAD was moved to the branch.
+    Switched {
+        actions: Vec<BranchAction>,
+    },
+    /// The branch already is the current one.
+    Current,
+    /// The branch was deleted, and from the remote if pushed.
+    Deleted {
+        pushed: bool,
+    },
+    /// Either the local or the remote branch was deleted, the other one was kept.
+    PartlyDeleted {
+        pushed: bool,
+        reason: String,
+    },
+    /// The branch does not exist in the repository.
+    Missing,
+    /// The repository was left untouched.
+    Skipped(String),
+    Failed(String),
+}
+
+impl fmt::Display for BranchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchOutcome::Created { pushed: true } => write!(f, "created and pushed"),
+            BranchOutcome::Created { pushed: false } => write!(f, "created"),
+            BranchOutcome::Existing { pushed: true } => write!(f, "already exists, pushed"),
+            BranchOutcome::Existing { pushed: false } => write!(f, "already exists"),
+            BranchOutcome::Switched { actions } => write!(
+                f,
+                "{}",
+                actions
+                    .iter()
+                    .map(|action| action.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            BranchOutcome::Current => write!(f, "already current"),
+            BranchOutcome::Deleted { pushed: true } => write!(f, "deleted, from the remote too"),
+            BranchOutcome::Deleted { pushed: false } => write!(f, "deleted"),
+            BranchOutcome::PartlyDeleted {
+                pushed: true,
+                reason,
+            } => write!(f, "deleted from the remote only, {}", reason),
+            BranchOutcome::PartlyDeleted {
+                pushed: false,
+                reason,
+            } => write!(f, "deleted locally only, {}", reason),
+            BranchOutcome::Missing => write!(f, "does not exist"),
+            BranchOutcome::Skipped(reason) => write!(f, "skipped, {}", reason),
+            BranchOutcome::Failed(err) => write!(f, "failed, {}", err),
+        }
+    }
+}
@@ -1,5 +1,11 @@
-use super::{File, Pool, Repository, Result, Settings};
-use git2::{Cred, RemoteCallbacks};
+use super::{
+    CredentialsProvider, Error, File, Pool, Repository, RepositoryState, Result, Selection,
+    Settings, LOCAL_BRANCH_PREFIX,
+};
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, Signature};
+use rayon::prelude::*;
+use std::fmt;
 use std::io::prelude::*;
 
 pub type CheckoutResult = Result<Checkout>;
@@ -7,27 +13,51 @@ pub type CheckoutResult = Result<Checkout>;
 pub struct CheckoutManager;
 
 impl CheckoutManager {
-    pub fn checkout(settings: &mut Settings) -> Result<Checkout> {
+    pub fn checkout(
+        settings: &mut Settings,
+        jobs: Option<usize>,
+        autostash: bool,
+        selection: &Selection,
+    ) -> Result<Checkout> {
         let pool = Pool::from_current(settings)?;
 
-        let data = CheckoutManager::build_checkout_worker_data(&pool)?;
+        let mut data = CheckoutManager::build_checkout_worker_data(&pool, selection)?;
 
-        // FIXME: multi thread this part.
-        // https://github.com/rust-lang/git2-rs/issues/329
-        for repository in &data.clone {
-            CheckoutManager::clone_repository(&pool, repository)?;
+        if !autostash {
+            let mut repositories = Vec::new();
+            for branch in &data.branches {
+                let git = git2::Repository::open(branch.repository.full_path(&pool.root))?;
+                if RepositoryState::is_dirty(&git)? {
+                    repositories.push(branch.repository.to_string());
+                }
+            }
+            if !repositories.is_empty() {
+                return Err(Error::DirtyRepositories { repositories }.into());
+            }
         }
 
-        for branch in &data.branches {
-            let git = git2::Repository::open(branch.repository.full_path(&pool.root))?;
-
-            let next_rev = git.revparse_single(&branch.next)?;
-            let next_tree = next_rev.peel_to_tree()?;
+        // `git2` contexts cannot be shared between threads, see
+        // https://github.com/rust-lang/git2-rs/issues/329
+        // Each worker builds its own context when cloning, so a failed
+        // clone only affects its own repository.
+        let (workers, throttle) = pool.workers(settings, jobs)?;
 
-            let mut index = git.index()?;
-            index.read_tree(&next_tree)?;
+        let settings = &*settings;
+        workers.install(|| {
+            data.clone.par_iter_mut().for_each(|clone| {
+                let _permit = throttle.acquire(clone.repository.fetch.host());
+                match CheckoutManager::clone_repository(settings, &pool, &clone.repository) {
+                    Ok(found) => clone.missing_branch = !found,
+                    Err(err) => clone.error = Some(err.to_string()),
+                }
+            })
+        });
 
-            git.checkout_index(Some(&mut index), None)?;
+        // A failed switch only affects its own repository.
+        for branch in &mut data.branches {
+            branch.error = CheckoutManager::switch_branch(&pool, branch, autostash)
+                .err()
+                .map(|err| err.to_string());
         }
 
         for file in pool.files {
@@ -40,13 +70,10 @@ impl CheckoutManager {
             };
 
             if path.is_file() {
-                match file.checksum {
-                    Some(checksum) => {
-                        if File::checksum(&path)? == checksum {
-                            continue;
-                        }
+                if let Some(checksum) = file.checksum {
+                    if File::checksum(&path)? == checksum {
+                        continue;
                     }
-                    None => (),
                 }
             }
 
@@ -56,12 +83,20 @@ impl CheckoutManager {
         Ok(data)
     }
 
-    fn build_checkout_worker_data(pool: &Pool) -> CheckoutResult {
+    fn build_checkout_worker_data(pool: &Pool, selection: &Selection) -> CheckoutResult {
         let mut clone = Vec::new();
         let mut branches = Vec::new();
-        for repository in &pool.repositories {
+        for repository in pool
+            .repositories
+            .iter()
+            .filter(|repository| selection.matches(pool, repository))
+        {
             if !repository.full_path(&pool.root).is_dir() {
-                clone.push(repository.clone());
+                clone.push(CloneCheckout {
+                    repository: repository.clone(),
+                    missing_branch: false,
+                    error: None,
+                });
             } else {
                 let existing_repository =
                     Repository::from_path(&pool.root, repository.full_path(&pool.root))?;
@@ -72,6 +107,8 @@ impl CheckoutManager {
                         repository: repository.clone(),
                         current,
                         next,
+                        actions: Vec::new(),
+                        error: None,
                     })
                 }
             }
@@ -81,25 +118,114 @@ impl CheckoutManager {
         Ok(data)
     }
 
+    pub(crate) fn switch_branch(
+        pool: &Pool,
+        branch: &mut BranchCheckout,
+        autostash: bool,
+    ) -> Result<()> {
+        let mut git = git2::Repository::open(branch.repository.full_path(&pool.root))?;
+
+        let stashed = autostash && RepositoryState::is_dirty(&git)?;
+        if stashed {
+            let signature = git
+                .signature()
+                .or_else(|_| Signature::now("gmux", "gmux"))?;
+            git.stash_save(&signature, "gmux autostash", None)?;
+            branch.actions.push(BranchAction::Stashed);
+        }
+
+        let switched = CheckoutManager::switch_head(&git, branch);
+
+        // Popping the stash back is attempted even if the switch failed,
+        // so the work in progress is never left behind in the stash list.
+        // The stash is only dropped once it applied without conflicts.
+        if stashed {
+            let applied = git.stash_apply(0, None).is_ok() && !git.index()?.has_conflicts();
+            if applied {
+                git.stash_drop(0)?;
+                branch.actions.push(BranchAction::Unstashed);
+            } else {
+                branch.actions.push(BranchAction::StashConflict);
+            }
+        }
+
+        switched
+    }
+
+    fn switch_head(git: &git2::Repository, branch: &mut BranchCheckout) -> Result<()> {
+        let name = branch.next.trim_start_matches(LOCAL_BRANCH_PREFIX);
+        let upstream_name = format!("{}/{}", branch.repository.fetch.name, name);
+
+        let mut local = match git.find_branch(name, BranchType::Local) {
+            Ok(local) => local,
+            Err(_) => {
+                let upstream = git.find_branch(&upstream_name, BranchType::Remote)?;
+                let commit = upstream.get().peel_to_commit()?;
+                branch.actions.push(BranchAction::Created);
+                git.branch(name, &commit, false)?
+            }
+        };
+
+        if local.upstream().is_err() && git.find_branch(&upstream_name, BranchType::Remote).is_ok()
+        {
+            local.set_upstream(Some(&upstream_name))?;
+            branch.actions.push(BranchAction::Tracked);
+        }
+
+        let tree = local.get().peel_to_tree()?;
+        git.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        git.set_head(&format!("{}{}", LOCAL_BRANCH_PREFIX, name))?;
+        branch.actions.push(BranchAction::Switched);
+
+        Ok(())
+    }
+
     // https://docs.rs/git2/0.13.12/git2/build/struct.RepoBuilder.html
-    fn clone_repository(pool: &Pool, repository: &Repository) -> Result<()> {
+    /// Clone a repository on the remote default branch, then switch to the
+    /// recorded branch.
+    ///
+    /// Returns whether the recorded branch exists on the fetch remote,
+    /// the default branch is kept otherwise.
+    fn clone_repository(settings: &Settings, pool: &Pool, repository: &Repository) -> Result<bool> {
         let into = repository.full_path(&pool.root);
 
-        // TODO: Add HTTPS options ?
-
-        let mut callbacks = RemoteCallbacks::new();
-        callbacks.credentials(|_url, username_from_url, _allowed_types| {
-            Cred::ssh_key_from_agent(username_from_url.expect("Could not guess username from URL."))
-        });
+        let mut credentials = CredentialsProvider::new(settings);
 
         let mut fo = git2::FetchOptions::new();
-        fo.remote_callbacks(callbacks);
+        fo.remote_callbacks(credentials.callbacks());
 
         let mut builder = git2::build::RepoBuilder::new();
         builder.fetch_options(fo);
+        builder.remote_create(|git, _name, url| git.remote(&repository.fetch.name, url));
 
-        builder.clone(&repository.fetch.url, &into)?;
-        Ok(())
+        let git = builder.clone(&repository.fetch.url, &into)?;
+
+        for remote in &repository.remotes {
+            if remote.name != repository.fetch.name {
+                git.remote(&remote.name, &remote.url)?;
+            }
+        }
+
+        let name = repository.branch_name();
+        let upstream_name = format!("{}/{}", repository.fetch.name, name);
+        let upstream = match git.find_branch(&upstream_name, BranchType::Remote) {
+            Ok(upstream) => upstream,
+            Err(_) => return Ok(false),
+        };
+
+        let mut local = match git.find_branch(name, BranchType::Local) {
+            Ok(local) => local,
+            Err(_) => git.branch(name, &upstream.get().peel_to_commit()?, false)?,
+        };
+        local.set_upstream(Some(&upstream_name))?;
+
+        if !local.is_head() {
+            let tree = local.get().peel_to_tree()?;
+            git.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))?;
+            git.set_head(&format!("{}{}", LOCAL_BRANCH_PREFIX, name))?;
+        }
+
+        Ok(true)
     }
 }
 
@@ -107,11 +233,37 @@ impl CheckoutManager {
 /// A checkout command worker data.
 pub struct Checkout {
     /// Missing repositories to clone with the checkout command.
-    pub clone: Vec<Repository>,
+    pub clone: Vec<CloneCheckout>,
     /// List of branches to checkout.
     pub branches: Vec<BranchCheckout>,
 }
 
+impl Checkout {
+    /// Whether every repository was checked out.
+    pub fn is_ok(&self) -> bool {
+        self.clone.iter().all(|clone| clone.is_ok())
+            && self.branches.iter().all(|branch| branch.is_ok())
+    }
+}
+
+#[derive(Debug)]
+/// A repository clone worker data.
+pub struct CloneCheckout {
+    /// Repository to clone.
+    pub repository: Repository,
+    /// Whether the recorded branch is missing from the fetch remote,
+    /// the remote default branch being checked out instead.
+    pub missing_branch: bool,
+    /// Reason of the failure, if the clone did not succeed.
+    pub error: Option<String>,
+}
+
+impl CloneCheckout {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
 #[derive(Debug)]
 /// A branch checkout worker data.
 pub struct BranchCheckout {
@@ -121,4 +273,44 @@ pub struct BranchCheckout {
     pub current: String,
     /// Name of the branch to checkout.
     pub next: String,
+    /// Actions taken to checkout the branch, in order.
+    pub actions: Vec<BranchAction>,
+    /// Reason of the failure, if the checkout did not succeed.
+    pub error: Option<String>,
+}
+
+impl BranchCheckout {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An action taken when checking out a branch.
+pub enum BranchAction {
+    /// The local branch did not exist and was created from its upstream.
+    Created,
+    /// The local branch was configured to track its upstream.
+    Tracked,
+    /// HEAD was moved to the local branch.
+    Switched,
+    /// Uncommitted changes were stashed before switching.
+    Stashed,
+    /// Stashed changes were restored after switching.
+    Unstashed,
+    /// Stashed changes conflicted with the new branch and were kept in the stash.
+    StashConflict,
+}
+
+impl fmt::Display for BranchAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchAction::Created => write!(f, "created"),
+            BranchAction::Tracked => write!(f, "tracked"),
+            BranchAction::Switched => write!(f, "switched"),
+            BranchAction::Stashed => write!(f, "stashed"),
+            BranchAction::Unstashed => write!(f, "unstashed"),
+            BranchAction::StashConflict => write!(f, "stash conflict"),
+        }
+    }
 }
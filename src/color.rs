@@ -0,0 +1,30 @@
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// When gmux output should be colored.
+pub enum ColorChoice {
+    /// Color output on a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    /// Always color output.
+    Always,
+    /// Never color output.
+    Never,
+}
+
+impl ColorChoice {
+    /// Enable or disable colors for the whole process, returning whether
+    /// output is colored.
+    pub fn apply(self) -> bool {
+        let enabled = match self {
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && atty::is(atty::Stream::Stdout)
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        colored::control::set_override(enabled);
+        enabled
+    }
+}
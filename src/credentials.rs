@@ -0,0 +1,238 @@
+use super::Settings;
+use dirs::home_dir;
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const DEFAULT_SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A way of authenticating against a remote.
+pub enum CredentialMethod {
+    /// Keys provided by a running ssh-agent.
+    SshAgent,
+    /// Private key files, see `Settings::ssh_keys`.
+    SshKey,
+    /// Username and password provided by `git credential fill`.
+    GitCredential,
+    /// Default credentials, used for local and file remotes
+    /// or NTLM/Negotiate authentication.
+    Default,
+}
+
+impl fmt::Display for CredentialMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialMethod::SshAgent => write!(f, "ssh-agent"),
+            CredentialMethod::SshKey => write!(f, "ssh-key"),
+            CredentialMethod::GitCredential => write!(f, "git-credential"),
+            CredentialMethod::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Attempt {
+    SshAgent,
+    SshKey(PathBuf),
+    GitCredential,
+    Default,
+}
+
+impl Attempt {
+    fn is_allowed(&self, allowed_types: CredentialType) -> bool {
+        match self {
+            Attempt::SshAgent | Attempt::SshKey(_) => {
+                allowed_types.contains(CredentialType::SSH_KEY)
+            }
+            Attempt::GitCredential => allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT),
+            Attempt::Default => allowed_types.contains(CredentialType::DEFAULT),
+        }
+    }
+}
+
+impl fmt::Display for Attempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Attempt::SshAgent => write!(f, "{}", CredentialMethod::SshAgent),
+            Attempt::SshKey(path) => write!(f, "{} {}", CredentialMethod::SshKey, path.display()),
+            Attempt::GitCredential => write!(f, "{}", CredentialMethod::GitCredential),
+            Attempt::Default => write!(f, "{}", CredentialMethod::Default),
+        }
+    }
+}
+
+/// Provide credentials to `git2` remote operations.
+///
+/// Every configured method is tried in turn, each time the remote
+/// rejects the previous credentials. A provider keeps track of its
+/// attempts, so a new one should be created for each remote operation.
+pub struct CredentialsProvider {
+    executable: PathBuf,
+    attempts: Vec<Attempt>,
+    cursor: usize,
+    tried: Vec<String>,
+}
+
+impl CredentialsProvider {
+    pub fn new(settings: &Settings) -> Self {
+        let ssh_keys = if settings.ssh_keys.is_empty() {
+            CredentialsProvider::default_ssh_keys()
+        } else {
+            settings.ssh_keys.clone()
+        };
+
+        let mut attempts = Vec::new();
+        for method in &settings.credentials {
+            match method {
+                CredentialMethod::SshAgent => attempts.push(Attempt::SshAgent),
+                CredentialMethod::SshKey => attempts.extend(
+                    ssh_keys
+                        .iter()
+                        .filter(|path| path.is_file())
+                        .map(|path| Attempt::SshKey(path.clone())),
+                ),
+                CredentialMethod::GitCredential => attempts.push(Attempt::GitCredential),
+                CredentialMethod::Default => attempts.push(Attempt::Default),
+            }
+        }
+
+        CredentialsProvider {
+            executable: settings.executable.clone(),
+            attempts,
+            cursor: 0,
+            tried: Vec::new(),
+        }
+    }
+
+    /// Build remote callbacks asking this provider for credentials.
+    pub fn callbacks(&mut self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            self.credentials(url, username_from_url, allowed_types)
+        });
+        callbacks
+    }
+
+    fn credentials(
+        &mut self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed_types: CredentialType,
+    ) -> std::result::Result<Cred, git2::Error> {
+        let username = match username_from_url {
+            Some(username) => String::from(username),
+            None => env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_else(|_| String::from("git")),
+        };
+
+        // SSH remotes without a username in their URL first ask for one.
+        if allowed_types.contains(CredentialType::USERNAME) {
+            return Cred::username(&username);
+        }
+
+        while self.cursor < self.attempts.len() {
+            let attempt = self.attempts[self.cursor].clone();
+            self.cursor += 1;
+
+            if !attempt.is_allowed(allowed_types) {
+                continue;
+            }
+
+            let cred = match &attempt {
+                Attempt::SshAgent => Cred::ssh_key_from_agent(&username),
+                Attempt::SshKey(path) => Cred::ssh_key(&username, None, path, None),
+                Attempt::GitCredential => self.credential_fill(url, username_from_url),
+                Attempt::Default => Cred::default(),
+            };
+
+            self.tried.push(attempt.to_string());
+
+            if let Ok(cred) = cred {
+                return Ok(cred);
+            }
+        }
+
+        let message = if self.tried.is_empty() {
+            format!("No configured credential method applies to {}.", url)
+        } else {
+            format!(
+                "Authentication failed for {}, tried: {}.",
+                url,
+                self.tried.join(", ")
+            )
+        };
+        Err(git2::Error::from_str(&message))
+    }
+
+    /// Ask `git credential fill` for a username and password.
+    ///
+    /// Terminal prompts are disabled, remote operations may run
+    /// in parallel and would fight over the terminal.
+    fn credential_fill(
+        &self,
+        url: &str,
+        username: Option<&str>,
+    ) -> std::result::Result<Cred, git2::Error> {
+        let mut child = Command::new(&self.executable)
+            .args(["credential", "fill"])
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+
+        let mut request = format!("url={}\n", url);
+        if let Some(username) = username {
+            request.push_str(&format!("username={}\n", username));
+        }
+        request.push('\n');
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(request.as_bytes())
+                .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+        if !output.status.success() {
+            return Err(git2::Error::from_str("git credential fill failed."));
+        }
+
+        let mut username = None;
+        let mut password = None;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some(value) = line.strip_prefix("username=") {
+                username = Some(String::from(value));
+            } else if let Some(value) = line.strip_prefix("password=") {
+                password = Some(String::from(value));
+            }
+        }
+
+        match (username, password) {
+            (Some(username), Some(password)) => Cred::userpass_plaintext(&username, &password),
+            _ => Err(git2::Error::from_str(
+                "git credential fill did not return a username and password.",
+            )),
+        }
+    }
+
+    fn default_ssh_keys() -> Vec<PathBuf> {
+        match home_dir() {
+            Some(home) => DEFAULT_SSH_KEYS
+                .iter()
+                .map(|name| home.join(".ssh").join(name))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
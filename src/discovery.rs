@@ -4,7 +4,7 @@ use walkdir::WalkDir;
 
 pub struct DiscoveryManager;
 
-const GIT_DIR: &'static str = ".git";
+const GIT_DIR: &str = ".git";
 
 impl DiscoveryManager {
     pub fn discover(pool: &Pool) -> Result<Discovery> {
@@ -65,7 +65,15 @@ impl DiscoveryManager {
 
     pub fn discover_current(settings: &Settings) -> Result<Pool> {
         let mut pool = Pool::from_current(settings)?;
-        let discovery = DiscoveryManager::discover(&pool)?;
+        let mut discovery = DiscoveryManager::discover(&pool)?;
+        // Tags are user defined, keep those of the repositories found again.
+        for repository in &mut discovery.repositories {
+            if let Some(previous) = pool.repositories.iter().find(|previous| {
+                previous.path == repository.path && previous.name == repository.name
+            }) {
+                repository.tags = previous.tags.clone();
+            }
+        }
         pool.repositories = discovery.repositories;
         pool.files = discovery.files;
         pool.save(settings)?;
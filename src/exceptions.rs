@@ -8,9 +8,16 @@ pub enum Error {
     PoolAlreadyExists { label: String },
     PoolDoesNotExists { label: String },
     RepositoryDoesNotExists { path: PathBuf },
+    RepositoryNotInPool { repository: String },
+    AmbiguousRepository { repository: String, matches: Vec<String> },
     FileDoesNotExists { path: PathBuf },
     InvalidSettingsFile,
     NoCurrentPoolSet,
+    DirtyRepositories { repositories: Vec<String> },
+    InvalidFilter { pattern: String, reason: String },
+    InvalidQuery { query: String, reason: String },
+    InvalidTimeout { timeout: f64 },
+    PushRejected { refspec: String, reason: String },
 }
 
 impl fmt::Display for Error {
@@ -19,6 +26,10 @@ impl fmt::Display for Error {
             Error::PoolAlreadyExists { label } => write!(f, "Pool '{}' already exists.", &label),
             Error::PoolDoesNotExists { label } => write!(f, "Pool '{}' does not exists.", &label),
             Error::RepositoryDoesNotExists { path } => write!(f, "Repository '{}' does not exists.", &path.display()),
+            Error::RepositoryNotInPool { repository } => write!(f, "Repository '{}' is not part of the pool.", repository),
+            Error::AmbiguousRepository { repository, matches } => {
+                write!(f, "Repository '{}' is ambiguous, use one of: {}", repository, matches.join(", "))
+            }
             Error::FileDoesNotExists { path } => write!(f, "File '{}' does not exists.", &path.display()),
             Error::InvalidSettingsFile => {
                 write!(f, "Settings file is invalid, do you have a home folder ?")
@@ -26,6 +37,21 @@ impl fmt::Display for Error {
             Error::NoCurrentPoolSet => {
                 write!(f, "No pool is currently set, create one using `gmux pool new` or set an existing one with `gmux pool set`.")
             }
+            Error::DirtyRepositories { repositories } => {
+                write!(f, "Repositories have uncommitted changes, commit them or use `--autostash`: {}", repositories.join(", "))
+            }
+            Error::InvalidFilter { pattern, reason } => {
+                write!(f, "Filter '{}' is not a valid regular expression:\n{}", pattern, reason)
+            }
+            Error::InvalidQuery { query, reason } => {
+                write!(f, "Query '{}' is invalid: {}.", query, reason)
+            }
+            Error::InvalidTimeout { timeout } => {
+                write!(f, "Timeout '{}' is invalid, it must be a positive number of seconds.", timeout)
+            }
+            Error::PushRejected { refspec, reason } => {
+                write!(f, "Push of '{}' was rejected: {}", refspec, reason)
+            }
         }
     }
 }
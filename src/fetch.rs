@@ -0,0 +1,217 @@
+use super::{
+    CredentialsProvider, HostThrottle, Pool, Repository, Result, Selection, Settings,
+    REMOTE_BRANCH_PREFIX,
+};
+use git2::{FetchOptions, FetchPrune, Oid};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct FetchManager;
+
+impl FetchManager {
+    /// Fetch every recorded remote of the selected repositories, in parallel.
+    ///
+    /// `progress` is called as each repository completes, with the number
+    /// of completed repositories and the total.
+    pub fn fetch(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        prune: bool,
+        progress: &(dyn Fn(&RepositoryFetch, usize, usize) + Sync),
+    ) -> Result<Vec<RepositoryFetch>> {
+        let pool = Pool::from_current(settings)?;
+
+        let repositories: Vec<&Repository> = pool
+            .repositories
+            .iter()
+            .filter(|repository| selection.matches(&pool, repository))
+            .collect();
+
+        // `git2` contexts cannot be shared between threads, each worker
+        // opens its own repository.
+        let (workers, throttle) = pool.workers(settings, jobs)?;
+        let completed = AtomicUsize::new(0);
+
+        let mut fetches: Vec<RepositoryFetch> = workers.install(|| {
+            repositories
+                .par_iter()
+                .map(|repository| {
+                    let fetch = FetchManager::fetch_repository(
+                        settings, &pool, &throttle, repository, prune,
+                    );
+                    let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    progress(&fetch, completed, repositories.len());
+                    fetch
+                })
+                .collect()
+        });
+        fetches.sort_by(|a, b| a.repository.cmp(&b.repository));
+
+        Ok(fetches)
+    }
+
+    fn fetch_repository(
+        settings: &Settings,
+        pool: &Pool,
+        throttle: &HostThrottle,
+        repository: &Repository,
+        prune: bool,
+    ) -> RepositoryFetch {
+        let mut fetch = RepositoryFetch {
+            repository: repository.to_string(),
+            remotes: Vec::new(),
+            error: None,
+        };
+
+        let git = match git2::Repository::open(repository.full_path(&pool.root)) {
+            Ok(git) => git,
+            Err(err) => {
+                fetch.error = Some(err.message().to_string());
+                return fetch;
+            }
+        };
+
+        for remote in &repository.remotes {
+            let _permit = throttle.acquire(remote.host());
+            let remote_fetch = match FetchManager::fetch_remote(settings, &git, &remote.name, prune)
+            {
+                Ok(remote_fetch) => remote_fetch,
+                Err(err) => RemoteFetch {
+                    remote: remote.name.clone(),
+                    error: Some(err.to_string()),
+                    ..RemoteFetch::default()
+                },
+            };
+            fetch.remotes.push(remote_fetch);
+        }
+
+        fetch
+    }
+
+    /// Fetch a remote with its configured refspecs, and compare its branches.
+    pub(crate) fn fetch_remote(
+        settings: &Settings,
+        git: &git2::Repository,
+        remote: &str,
+        prune: bool,
+    ) -> Result<RemoteFetch> {
+        let before = FetchManager::remote_branches(git, remote)?;
+
+        let mut credentials = CredentialsProvider::new(settings);
+        let mut fo = FetchOptions::new();
+        fo.remote_callbacks(credentials.callbacks());
+        fo.prune(if prune {
+            FetchPrune::On
+        } else {
+            FetchPrune::Unspecified
+        });
+
+        git.find_remote(remote)?
+            .fetch(&[] as &[&str], Some(&mut fo), None)?;
+
+        let after = FetchManager::remote_branches(git, remote)?;
+
+        let mut remote_fetch = RemoteFetch {
+            remote: String::from(remote),
+            ..RemoteFetch::default()
+        };
+        for (branch, oid) in &after {
+            match before.get(branch) {
+                None => remote_fetch.new_branches.push(branch.clone()),
+                Some(previous) if previous != oid => {
+                    // A force push may leave no new commit behind.
+                    let (ahead, _) = git.graph_ahead_behind(*oid, *previous)?;
+                    remote_fetch.new_commits += ahead;
+                }
+                Some(_) => (),
+            }
+        }
+        remote_fetch.deleted_branches = before
+            .keys()
+            .filter(|branch| !after.contains_key(*branch))
+            .cloned()
+            .collect();
+
+        Ok(remote_fetch)
+    }
+
+    /// Remote tracking branches of a remote, by short name.
+    fn remote_branches(git: &git2::Repository, remote: &str) -> Result<BTreeMap<String, Oid>> {
+        let prefix = format!("{}{}/", REMOTE_BRANCH_PREFIX, remote);
+        let mut branches = BTreeMap::new();
+        for reference in git.references_glob(&format!("{}*", prefix))? {
+            let reference = reference?;
+            let (name, oid) = match (reference.name(), reference.target()) {
+                (Some(name), Some(oid)) => (name, oid),
+                // Symbolic references such as `origin/HEAD`.
+                _ => continue,
+            };
+            branches.insert(String::from(name.trim_start_matches(&prefix)), oid);
+        }
+        Ok(branches)
+    }
+}
+
+#[derive(Debug)]
+/// Result of fetching the remotes of a repository.
+pub struct RepositoryFetch {
+    /// Repository path, relative to the pool root.
+    pub repository: String,
+    pub remotes: Vec<RemoteFetch>,
+    /// Reason the repository could not be opened.
+    pub error: Option<String>,
+}
+
+impl RepositoryFetch {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none() && self.remotes.iter().all(|remote| remote.error.is_none())
+    }
+
+    /// Whether a remote brought new commits, or new or deleted branches.
+    pub fn is_updated(&self) -> bool {
+        self.remotes.iter().any(|remote| remote.is_updated())
+    }
+}
+
+#[derive(Debug, Default)]
+/// Result of fetching a single remote.
+pub struct RemoteFetch {
+    pub remote: String,
+    /// Commits added to the existing remote branches.
+    pub new_commits: usize,
+    pub new_branches: Vec<String>,
+    /// Branches pruned since they were removed from the remote.
+    pub deleted_branches: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl RemoteFetch {
+    pub fn is_updated(&self) -> bool {
+        self.new_commits > 0 || !self.new_branches.is_empty() || !self.deleted_branches.is_empty()
+    }
+}
+
+impl fmt::Display for RemoteFetch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut changes = Vec::new();
+        if self.new_commits > 0 {
+            changes.push(format!("{} new commits", self.new_commits));
+        }
+        if !self.new_branches.is_empty() {
+            changes.push(format!("new branches {}", self.new_branches.join(", ")));
+        }
+        if !self.deleted_branches.is_empty() {
+            changes.push(format!(
+                "deleted branches {}",
+                self.deleted_branches.join(", ")
+            ));
+        }
+        if changes.is_empty() {
+            changes.push(String::from("up to date"));
+        }
+        write!(f, "{}: {}", self.remote, changes.join(", "))
+    }
+}
@@ -0,0 +1,312 @@
+use super::{Error, Result};
+use colored::*;
+use regex::Regex;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::process::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// Output streams targeted by filters.
+pub enum FilterStream {
+    /// Filter both the standard output and error.
+    #[default]
+    Both,
+    /// Only filter the standard output, the standard error is kept as is.
+    Stdout,
+    /// Only filter the standard error, the standard output is kept as is.
+    Stderr,
+}
+
+#[derive(Debug, Default)]
+/// Patterns selecting the repositories output to print.
+///
+/// Patterns are compiled once, when the filters are created.
+pub struct Filters {
+    /// Keep outputs matching any of these patterns.
+    include: Vec<Regex>,
+    /// Drop outputs matching any of these patterns.
+    exclude: Vec<Regex>,
+    /// Streams the patterns apply to.
+    stream: FilterStream,
+    /// Match patterns against each line instead of the whole output.
+    lines: bool,
+}
+
+impl Filters {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        stream: FilterStream,
+        lines: bool,
+    ) -> Result<Self> {
+        Ok(Filters {
+            include: Filters::compile(include)?,
+            exclude: Filters::compile(exclude)?,
+            stream,
+            lines,
+        })
+    }
+
+    fn compile(patterns: &[String]) -> Result<Vec<Regex>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|err| {
+                    Error::InvalidFilter {
+                        pattern: pattern.clone(),
+                        reason: err.to_string(),
+                    }
+                    .into()
+                })
+            })
+            .collect()
+    }
+
+    /// Filter a whole output, or its lines in line mode.
+    fn filter(&self, message: String) -> Option<String> {
+        if !self.lines {
+            return Some(message).filter(|message| self.is_match(message));
+        }
+
+        let lines: Vec<&str> = message.lines().filter(|line| self.is_match(line)).collect();
+        if lines.is_empty() {
+            return None;
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// Whether any pattern was given.
+    pub(crate) fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        if self.exclude.iter().any(|pattern| pattern.is_match(text)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(text))
+    }
+}
+
+#[derive(Debug)]
+/// Command output kept by the filters.
+///
+/// Outputs which are not valid UTF-8 are filtered and displayed lossily,
+/// while `write_to` writes the standard output bytes as is when it is
+/// kept whole.
+pub(crate) struct FilteredOutput {
+    out: Option<String>,
+    err: Option<String>,
+    /// Raw standard output, when kept whole and not valid UTF-8.
+    raw_out: Option<Vec<u8>>,
+    /// Whether a stream targeted by the filters was kept.
+    matched: bool,
+}
+
+impl FilteredOutput {
+    pub(crate) fn from(output: &Output, filters: &Filters) -> Self {
+        let filter_out = filters.stream != FilterStream::Stderr;
+        let filter_err = filters.stream != FilterStream::Stdout;
+
+        let mut out = FilteredOutput::bytes_to_string(&output.stdout);
+        if filter_out {
+            out = out.and_then(|message| filters.filter(message));
+        }
+        let mut err = FilteredOutput::bytes_to_string(&output.stderr);
+        if filter_err {
+            err = err.and_then(|message| filters.filter(message));
+        }
+
+        // Without patterns, any output is a match.
+        let matched = if filters.is_active() {
+            (filter_out && out.is_some()) || (filter_err && err.is_some())
+        } else {
+            out.is_some() || err.is_some()
+        };
+
+        let kept_whole = !filter_out || !filters.lines;
+        let raw_out = Some(&output.stdout)
+            .filter(|_| out.is_some() && kept_whole)
+            .filter(|bytes| std::str::from_utf8(bytes).is_err())
+            .cloned();
+
+        FilteredOutput {
+            out,
+            err,
+            raw_out,
+            matched,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.out.is_none() && self.err.is_none()
+    }
+
+    pub(crate) fn is_match(&self) -> bool {
+        self.matched
+    }
+
+    /// Write the output, with the raw standard output if it is not valid UTF-8.
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let raw_out = match &self.raw_out {
+            Some(raw_out) => raw_out,
+            None => return write!(writer, "{}", self),
+        };
+
+        writer.write_all(raw_out.trim_ascii())?;
+        writer.write_all(b"\n")?;
+        if let Some(err) = &self.err {
+            writeln!(writer, "{}", err.red().trim())?;
+        }
+        Ok(())
+    }
+
+    fn bytes_to_string(bytes: &[u8]) -> Option<String> {
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+impl fmt::Display for FilteredOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "");
+        }
+
+        let mut message = String::from("");
+
+        if let Some(out) = &self.out {
+            message.push_str(&format!("{}\n", out.trim()));
+        }
+
+        if let Some(err) = &self.err {
+            message.push_str(&format!("{}\n", err.red().trim()));
+        }
+
+        write!(f, "{}", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+    #[cfg(windows)]
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn output(stdout: &[u8], stderr: &[u8]) -> Output {
+        Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.to_vec(),
+            stderr: stderr.to_vec(),
+        }
+    }
+
+    fn filters(include: &[&str], exclude: &[&str], stream: FilterStream, lines: bool) -> Filters {
+        let patterns = |patterns: &[&str]| -> Vec<String> {
+            patterns
+                .iter()
+                .map(|pattern| String::from(*pattern))
+                .collect()
+        };
+        Filters::new(&patterns(include), &patterns(exclude), stream, lines).unwrap()
+    }
+
+    fn written(filtered: &FilteredOutput) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        filtered.write_to(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn keeps_any_output_without_patterns() {
+        let filters = Filters::default();
+
+        let filtered = FilteredOutput::from(&output(b"", b"warning\n"), &filters);
+        assert!(filtered.is_match());
+        assert_eq!(filtered.to_string(), format!("{}\n", "warning".red()));
+
+        let filtered = FilteredOutput::from(&output(b"", b""), &filters);
+        assert!(filtered.is_empty());
+        assert!(!filtered.is_match());
+    }
+
+    #[test]
+    fn matches_any_include_pattern() {
+        let filters = filters(&["^main$", "develop"], &[], FilterStream::Both, false);
+        assert!(FilteredOutput::from(&output(b"develop\n", b""), &filters).is_match());
+        assert!(FilteredOutput::from(&output(b"main", b""), &filters).is_match());
+        assert!(!FilteredOutput::from(&output(b"feature\n", b""), &filters).is_match());
+    }
+
+    #[test]
+    fn exclude_patterns_win_over_include_ones() {
+        let filters = filters(&["main"], &["behind", "ahead"], FilterStream::Both, false);
+        assert!(FilteredOutput::from(&output(b"main\n", b""), &filters).is_match());
+        assert!(!FilteredOutput::from(&output(b"main ahead 1\n", b""), &filters).is_match());
+        assert!(!FilteredOutput::from(&output(b"main behind 2\n", b""), &filters).is_match());
+    }
+
+    #[test]
+    fn filters_each_line() {
+        let filters = filters(&["^M "], &["Cargo.lock"], FilterStream::Both, true);
+        let filtered = FilteredOutput::from(
+            &output(b"M src/lib.rs\n?? notes\nM Cargo.lock\nM README.md\n", b""),
+            &filters,
+        );
+        assert!(filtered.is_match());
+        assert_eq!(filtered.to_string(), "M src/lib.rs\nM README.md\n");
+
+        let filtered = FilteredOutput::from(&output(b"?? notes\n", b""), &filters);
+        assert!(!filtered.is_match());
+    }
+
+    #[test]
+    fn filters_only_the_selected_stream() {
+        let stdout = filters(&["nomatch"], &[], FilterStream::Stdout, false);
+        let filtered = FilteredOutput::from(&output(b"out\n", b"err\n"), &stdout);
+        assert!(!filtered.is_match());
+        assert_eq!(filtered.to_string(), format!("{}\n", "err".red()));
+
+        let stdout = filters(&["out"], &[], FilterStream::Stdout, false);
+        assert!(FilteredOutput::from(&output(b"out\n", b"err\n"), &stdout).is_match());
+
+        let stderr = filters(&["err"], &[], FilterStream::Stderr, false);
+        let filtered = FilteredOutput::from(&output(b"out\n", b"err\n"), &stderr);
+        assert!(filtered.is_match());
+        assert!(!FilteredOutput::from(&output(b"err\n", b"out\n"), &stderr).is_match());
+    }
+
+    #[test]
+    fn writes_raw_output_kept_whole() {
+        let latin1 = b"caf\xe9\n";
+
+        let filtered = FilteredOutput::from(&output(latin1, b""), &Filters::default());
+        assert_eq!(written(&filtered), latin1.to_vec());
+
+        let filters = filters(&["caf"], &[], FilterStream::Both, false);
+        let filtered = FilteredOutput::from(&output(latin1, b""), &filters);
+        assert!(filtered.is_match());
+        assert_eq!(written(&filtered), latin1.to_vec());
+    }
+
+    #[test]
+    fn writes_lossy_lines_when_filtering_lines() {
+        let filters = filters(&["caf"], &[], FilterStream::Both, true);
+        let filtered = FilteredOutput::from(&output(b"caf\xe9\nother\n", b""), &filters);
+        assert_eq!(written(&filtered), "caf\u{fffd}\n".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let err = Filters::new(&[String::from("(")], &[], FilterStream::Both, false).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Filter '(' is not a valid regular expression"));
+    }
+}
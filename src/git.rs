@@ -1,166 +1,785 @@
-use super::{Pool, Result, Settings};
+use super::supervisor;
+use super::{
+    FilteredOutput, Filters, HostThrottle, KillReason, Pool, Repository, Result, Selection,
+    Settings,
+};
 use colored::*;
 use rayon::prelude::*;
-use regex::Regex;
+use serde::Serialize;
 use std::fmt;
+use std::io;
 use std::io::prelude::*;
 use std::path::PathBuf;
 use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 struct CommandOutput {
+    pool: String,
+    name: String,
+    /// Repository path, relative to the pool root.
+    path: PathBuf,
+    /// Path shown to the user, prefixed with the pool label when
+    /// the command runs on several pools.
+    display_path: PathBuf,
     header: String,
-    output: std::io::Result<Output>,
+    /// Program and arguments that ran, empty if the command was skipped.
+    argv: Vec<String>,
+    duration: Duration,
+    /// Command output, `None` if the command was skipped.
+    output: Option<io::Result<Output>>,
+    /// Set when the command was killed before completing.
+    killed: Option<KillReason>,
+}
+
+impl CommandOutput {
+    fn new(pool: &Pool, repository: &Repository, prefixed: bool) -> Self {
+        let path = repository.path.join(&repository.name);
+        let display_path = if prefixed {
+            PathBuf::from(&pool.label).join(&path)
+        } else {
+            path.clone()
+        };
+        let header = format!("- {}\n", display_path.display());
+        CommandOutput {
+            pool: pool.label.clone(),
+            name: repository.name.clone(),
+            path,
+            display_path,
+            header,
+            argv: Vec::new(),
+            duration: Duration::default(),
+            output: None,
+            killed: None,
+        }
+    }
+
+    fn is_success(&self) -> bool {
+        matches!(&self.output, Some(Ok(output)) if output.status.success())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Order in which repositories output is printed.
+pub enum OutputOrder {
+    /// Print outputs sorted by repository path, once every command completed.
+    Path,
+    /// Print outputs as soon as each command completes.
+    Completion,
+}
+
+impl Default for OutputOrder {
+    /// Stream outputs on a terminal, keep them reproducible otherwise.
+    fn default() -> Self {
+        if atty::is(atty::Stream::Stdout) {
+            OutputOrder::Completion
+        } else {
+            OutputOrder::Path
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// Format of the repositories output.
+pub enum OutputFormat {
+    /// A header per repository, followed by its filtered output.
+    #[default]
+    Text,
+    /// A single JSON array of records, printed once every command completed.
+    Json,
+    /// A JSON record per line, printed as soon as each command completes.
+    Ndjson,
+}
+
+#[derive(Debug, Clone)]
+/// A command to run in every repository.
+pub enum RepositoryCommand {
+    /// Arguments forwarded to the git executable.
+    Git(Vec<String>),
+    /// A program followed by its arguments, run from the repository directory.
+    Exec(Vec<String>),
+    /// A command line run through the system shell, from the repository directory.
+    Shell(String),
+}
+
+#[derive(Debug, Default)]
+/// Options of a command forwarded to every repository.
+pub struct CallOptions {
+    /// Criteria selecting the repositories to run the command on.
+    pub selection: Selection,
+    /// Patterns selecting the outputs to print.
+    pub filters: Filters,
+    /// Order in which repositories output is printed.
+    pub order: OutputOrder,
+    /// Format of the repositories output.
+    pub format: OutputFormat,
+    /// Stop running the command on new repositories after the first failure.
+    pub fail_fast: bool,
+    /// Number of parallel workers, overrides the pool and settings value.
+    pub jobs: Option<usize>,
+    /// Number of parallel workers per remote host, overrides the pool and settings value.
+    pub host_jobs: Option<usize>,
+    /// Run on one repository at a time, attached to the terminal.
+    pub interactive: bool,
+    /// Print each distinct output once, with the repositories producing it.
+    pub group: bool,
+    /// Kill commands running longer than this duration.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Answer to the prompt shown before each repository in interactive mode.
+enum Prompt {
+    Continue,
+    Skip,
+    Abort,
 }
 
 pub struct GitCaller {
     pub executable: PathBuf,
-    pub pool: Pool,
+    /// Pools whose repositories the commands run on.
+    pub pools: Vec<Pool>,
+    /// Default number of parallel workers, the number of CPUs if unset.
+    pub jobs: Option<usize>,
+    /// Default number of parallel workers per remote host, unlimited if unset.
+    pub host_jobs: Option<usize>,
+    /// Whether git commands should color their output, following gmux colors.
+    pub color: bool,
 }
 
 impl GitCaller {
-
     pub fn new(settings: &Settings) -> Result<Self> {
-        let executable = settings.executable.clone();
-        let pool = Pool::from_current(&settings)?;
-        Ok(GitCaller { executable, pool })
+        let pool = Pool::from_current(settings)?;
+        Ok(GitCaller::with_pools(settings, vec![pool]))
     }
 
-    pub fn call(
-        &self,
-        args: Vec<String>,
-        filter: Option<String>,
-        exclude_filter: Option<String>,
-    ) -> Result<()> {
-        let (sender, receiver) = channel();
+    /// Run commands on the repositories of every pool.
+    pub fn all_pools(settings: &Settings) -> Result<Self> {
+        let pools = Pool::list(settings)?;
+        Ok(GitCaller::with_pools(settings, pools))
+    }
 
-        self.pool
-            .repositories
-            .par_iter()
-            .for_each_with(sender, |s, repository| {
+    fn with_pools(settings: &Settings, pools: Vec<Pool>) -> Self {
+        // Pool specific limits only apply when a single pool is called.
+        let (jobs, host_jobs) = match pools.as_slice() {
+            [pool] => (
+                pool.jobs.or(settings.jobs),
+                pool.host_jobs.or(settings.host_jobs),
+            ),
+            _ => (settings.jobs, settings.host_jobs),
+        };
+        GitCaller {
+            executable: settings.executable.clone(),
+            pools,
+            jobs,
+            host_jobs,
+            color: colored::control::SHOULD_COLORIZE.should_colorize(),
+        }
+    }
 
-                let path = repository.full_path(&self.pool.root);
-                let path = match path.to_str() {
-                    None => return (),
-                    Some(path) => path,
-                };
+    /// Selected repositories of every called pool, along with their pool.
+    ///
+    /// Selection may read the repositories state, it runs in parallel.
+    fn targets(&self, options: &CallOptions) -> Vec<(&Pool, &Repository)> {
+        let targets: Vec<(&Pool, &Repository)> = self
+            .pools
+            .iter()
+            .flat_map(|pool| {
+                pool.repositories
+                    .iter()
+                    .map(move |repository| (pool, repository))
+            })
+            .collect();
+        targets
+            .into_par_iter()
+            .filter(|(pool, repository)| options.selection.matches(pool, repository))
+            .collect()
+    }
+
+    fn command_output(&self, pool: &Pool, repository: &Repository) -> CommandOutput {
+        CommandOutput::new(pool, repository, self.pools.len() > 1)
+    }
+
+    pub fn call(&self, command: &RepositoryCommand, options: &CallOptions) -> Result<CallSummary> {
+        // Interactive commands are attached to the terminal, Ctrl-C
+        // keeps its default behaviour there.
+        if options.interactive {
+            return self.call_interactive(command, options);
+        }
 
-                let mut repository_args = vec!["-C".into(), path.into()];
-                repository_args.append(&mut args.clone());
+        supervisor::handle_interrupts();
 
-                let exe = self.executable.clone();
-                let header = format!("- {}\n", repository.path.join(&repository.name).display());
+        let (sender, receiver) = channel();
+        let failed = AtomicBool::new(false);
+        let failed = &failed;
+
+        let mut summary = CallSummary::default();
 
-                let output = Command::new(exe).args(repository_args).output();
+        let workers = rayon::ThreadPoolBuilder::new()
+            .num_threads(options.jobs.or(self.jobs).unwrap_or(0))
+            .build()?;
+        let throttle = HostThrottle::new(options.host_jobs.or(self.host_jobs));
+        let throttle = &throttle;
+        let targets = self.targets(options);
 
-                s.send(CommandOutput { header, output })
-                    .expect("Cannot send git command output to channel receiver.");
+        // Commands run on a separate thread, so outputs can be
+        // printed while the remaining commands are still running.
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                workers.install(|| {
+                    targets
+                        .par_iter()
+                        .for_each_with(sender, |s, (pool, repository)| {
+                            let stopped = supervisor::is_interrupted()
+                                || (options.fail_fast && failed.load(Ordering::SeqCst));
+                            let command_output = if stopped {
+                                self.command_output(pool, repository)
+                            } else {
+                                let _permit = throttle.acquire(repository.fetch.host());
+                                let command_output = self.run(pool, repository, command, options);
+                                if !command_output.is_success() {
+                                    failed.store(true, Ordering::SeqCst);
+                                }
+                                command_output
+                            };
 
+                            s.send(command_output)
+                                .expect("Cannot send git command output to channel receiver.");
+                        })
+                })
             });
 
-        for command_output in receiver {
-            match command_output.output {
-                Ok(output) => {
-                    let filtered_output = FilteredOutput::from(output, &filter, &exclude_filter);
+            let mut printer = Printer::new(options);
 
-                    if filtered_output.is_empty() {
-                        continue;
+            match options.order {
+                OutputOrder::Completion => {
+                    for command_output in receiver {
+                        summary.record(&command_output);
+                        printer.print(&command_output);
                     }
+                }
+                OutputOrder::Path => {
+                    let mut command_outputs: Vec<CommandOutput> = receiver.iter().collect();
+                    command_outputs.sort_by(|a, b| a.display_path.cmp(&b.display_path));
+                    for command_output in command_outputs {
+                        summary.record(&command_output);
+                        printer.print(&command_output);
+                    }
+                }
+            }
+
+            printer.finish();
+        });
 
-                    let message = format!("{}\n{}", command_output.header.blue(), filtered_output);
+        summary.failures.sort_by(|a, b| a.path.cmp(&b.path));
+        summary.skipped.sort();
+        summary.interrupted = supervisor::is_interrupted();
+
+        Ok(summary)
+    }
 
-                    std::io::stdout()
-                        .write_all(&message.as_bytes())
-                        .expect("Could not write command output to stdout.");
+    /// Run the command on one repository at a time, attached to the terminal.
+    ///
+    /// The user is prompted before each repository, and can skip it
+    /// or abort the remaining ones. Outputs are not captured, so filters
+    /// and output formats do not apply.
+    fn call_interactive(
+        &self,
+        command: &RepositoryCommand,
+        options: &CallOptions,
+    ) -> Result<CallSummary> {
+        let mut summary = CallSummary::default();
+
+        let mut command_outputs: Vec<(&Pool, &Repository, CommandOutput)> = self
+            .targets(options)
+            .into_iter()
+            .map(|(pool, repository)| (pool, repository, self.command_output(pool, repository)))
+            .collect();
+        command_outputs.sort_by(|(_, _, a), (_, _, b)| a.display_path.cmp(&b.display_path));
+
+        let mut aborted = false;
+
+        for (pool, repository, mut command_output) in command_outputs {
+            if !aborted {
+                print!("{}", command_output.header.blue());
+                io::stdout().flush()?;
+
+                match GitCaller::prompt()? {
+                    Prompt::Continue => {
+                        let start = Instant::now();
+                        let status = self
+                            .process(pool, repository, command, options)
+                            .and_then(|mut process| process.status());
+                        command_output.duration = start.elapsed();
+                        command_output.output = Some(status.map(|status| Output {
+                            status,
+                            stdout: Vec::new(),
+                            stderr: Vec::new(),
+                        }));
+                        aborted = options.fail_fast && !command_output.is_success();
+                    }
+                    Prompt::Skip => (),
+                    Prompt::Abort => aborted = true,
                 }
-                Err(_) => (),
+            }
+
+            summary.record(&command_output);
+        }
+
+        summary.failures.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(summary)
+    }
+
+    /// Ask the user what to do with the next repository.
+    ///
+    /// Closing the standard input aborts the remaining repositories.
+    fn prompt() -> Result<Prompt> {
+        loop {
+            eprint!("[c]ontinue, [s]kip or [a]bort ? [c] ");
+            io::stderr().flush()?;
+
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer)? == 0 {
+                return Ok(Prompt::Abort);
+            }
+
+            match answer.trim().to_lowercase().as_str() {
+                "" | "c" | "continue" => return Ok(Prompt::Continue),
+                "s" | "skip" => return Ok(Prompt::Skip),
+                "a" | "abort" => return Ok(Prompt::Abort),
+                _ => continue,
             }
         }
-        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct FilteredOutput {
-    out: Option<String>,
-    err: Option<String>,
-}
+    fn run(
+        &self,
+        pool: &Pool,
+        repository: &Repository,
+        command: &RepositoryCommand,
+        options: &CallOptions,
+    ) -> CommandOutput {
+        let mut command_output = self.command_output(pool, repository);
 
-impl FilteredOutput {
-    fn from(output: Output, filter: &Option<String>, exclude: &Option<String>) -> Self {
-        let out = match FilteredOutput::bytes_to_string(&output.stdout) {
-            Some(message) => FilteredOutput::filter(message, filter, exclude),
-            None => None,
+        let start = Instant::now();
+        let output = match self.process(pool, repository, command, options) {
+            Ok(mut process) => {
+                command_output.argv = std::iter::once(process.get_program())
+                    .chain(process.get_args())
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+                let (output, killed) = supervisor::output(&mut process, options.timeout);
+                command_output.killed = killed;
+                output
+            }
+            Err(err) => Err(err),
         };
-        let err = match FilteredOutput::bytes_to_string(&output.stderr) {
-            Some(message) => FilteredOutput::filter(message, filter, exclude),
-            None => None,
+        command_output.duration = start.elapsed();
+        command_output.output = Some(output);
+
+        command_output
+    }
+
+    fn process(
+        &self,
+        pool: &Pool,
+        repository: &Repository,
+        command: &RepositoryCommand,
+        options: &CallOptions,
+    ) -> io::Result<Command> {
+        let path = repository.full_path(&pool.root);
+        let variables = GitCaller::variables(pool, repository);
+        let expand = |arg: &String| GitCaller::expand(arg, &variables, false);
+
+        let mut process = match command {
+            RepositoryCommand::Git(args) => {
+                // JSON records hold the raw output, and filters or groups
+                // match against it, keep escape codes out of them.
+                let color = self.color
+                    && options.format == OutputFormat::Text
+                    && !options.filters.is_active()
+                    && !options.group;
+                let mut process = Command::new(&self.executable);
+                process
+                    .arg("-c")
+                    .arg(format!(
+                        "color.ui={}",
+                        if color { "always" } else { "never" }
+                    ))
+                    .arg("-C")
+                    .arg(path)
+                    .args(args.iter().map(expand));
+                process
+            }
+            RepositoryCommand::Exec(args) => {
+                let (program, args) = args.split_first().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "No program to run.")
+                })?;
+                let mut process = Command::new(expand(program));
+                process.args(args.iter().map(expand)).current_dir(path);
+                process
+            }
+            RepositoryCommand::Shell(line) => {
+                let mut process = if cfg!(windows) {
+                    let mut process = Command::new("cmd");
+                    process.arg("/C");
+                    process
+                } else {
+                    let mut process = Command::new("sh");
+                    process.arg("-c");
+                    process
+                };
+                process
+                    .arg(GitCaller::expand(line, &variables, true))
+                    .current_dir(path);
+                process
+            }
         };
-        FilteredOutput { out, err }
+
+        for variable in &variables {
+            process.env(variable.env, &variable.value);
+        }
+
+        Ok(process)
     }
 
-    fn is_empty(&self) -> bool {
-        self.out.is_none() && self.err.is_none()
+    /// Values describing a repository, available to forwarded commands
+    /// both as `{placeholder}` arguments and environment variables.
+    fn variables(pool: &Pool, repository: &Repository) -> Vec<Variable> {
+        let full_path = repository.full_path(&pool.root);
+        vec![
+            Variable::new("name", "GMUX_REPO_NAME", repository.name.clone()),
+            Variable::new(
+                "path",
+                "GMUX_REPO_PATH",
+                repository.path.join(&repository.name).display().to_string(),
+            ),
+            Variable::new(
+                "full_path",
+                "GMUX_REPO_FULL_PATH",
+                full_path.display().to_string(),
+            ),
+            Variable::new(
+                "branch",
+                "GMUX_REPO_BRANCH",
+                String::from(repository.branch_name()),
+            ),
+            Variable::new(
+                "fetch_remote",
+                "GMUX_REPO_FETCH_REMOTE",
+                repository.fetch.name.clone(),
+            ),
+            Variable::new(
+                "fetch_url",
+                "GMUX_REPO_FETCH_URL",
+                repository.fetch.url.clone(),
+            ),
+            Variable::new("pool", "GMUX_POOL_LABEL", pool.label.clone()),
+            Variable::new(
+                "pool_root",
+                "GMUX_POOL_ROOT",
+                pool.root.display().to_string(),
+            ),
+        ]
     }
 
-    fn bytes_to_string(bytes: &Vec<u8>) -> Option<String> {
-        if bytes.is_empty() {
-            return None;
+    /// Replace known placeholders, other braces are left untouched
+    /// so arguments such as `@{u}` keep working.
+    ///
+    /// Values are quoted when expanded in a shell command line, so they
+    /// are read as a single word whatever characters they hold.
+    fn expand(arg: &str, variables: &[Variable], shell: bool) -> String {
+        let mut arg = String::from(arg);
+        for variable in variables {
+            let value = if shell {
+                GitCaller::shell_quote(&variable.value)
+            } else {
+                variable.value.clone()
+            };
+            arg = arg.replace(&format!("{{{}}}", variable.placeholder), &value);
         }
-        match std::str::from_utf8(bytes) {
-            Ok(string) => Some(String::from(string)),
-            Err(_) => None,
+        arg
+    }
+
+    #[cfg(not(windows))]
+    fn shell_quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+
+    #[cfg(windows)]
+    fn shell_quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+}
+
+/// Print repositories output in the requested format.
+struct Printer<'a> {
+    options: &'a CallOptions,
+    /// Records waiting to be printed as a single JSON array.
+    records: Vec<CommandRecord>,
+    /// Distinct outputs waiting to be printed, with the repositories producing them.
+    groups: Vec<(String, Vec<PathBuf>)>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(options: &'a CallOptions) -> Self {
+        Printer {
+            options,
+            records: Vec::new(),
+            groups: Vec::new(),
         }
     }
 
-    fn filter(
-        message: String,
-        filter: &Option<String>,
-        exclude: &Option<String>,
-    ) -> Option<String> {
-        match exclude {
-            Some(pattern) => {
-                if FilteredOutput::is_match(&message, &pattern) {
-                    return None;
+    fn print(&mut self, command_output: &CommandOutput) {
+        let output = match &command_output.output {
+            Some(Ok(output)) => Some(output),
+            _ => None,
+        };
+        let filtered_output =
+            output.map(|output| FilteredOutput::from(output, &self.options.filters));
+
+        match self.options.format {
+            OutputFormat::Text => {
+                let filtered_output = match filtered_output {
+                    Some(filtered_output) if filtered_output.is_match() => filtered_output,
+                    _ => return,
+                };
+
+                if self.options.group {
+                    let output = filtered_output.to_string();
+                    let path = command_output.display_path.clone();
+                    match self.groups.iter_mut().find(|(other, _)| *other == output) {
+                        Some((_, paths)) => paths.push(path),
+                        None => self.groups.push((output, vec![path])),
+                    }
+                    return;
                 }
+
+                let mut stdout = std::io::stdout().lock();
+                writeln!(stdout, "{}", command_output.header.blue())
+                    .and_then(|_| filtered_output.write_to(&mut stdout))
+                    .expect("Could not write command output to stdout.");
             }
-            None => (),
-        }
-        match filter {
-            Some(pattern) => {
-                if !FilteredOutput::is_match(&message, &pattern) {
-                    return None;
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                let matched = filtered_output.is_some_and(|output| output.is_match());
+                let record = CommandRecord::new(command_output, matched);
+
+                if self.options.format == OutputFormat::Json {
+                    self.records.push(record);
+                    return;
                 }
+
+                let mut stdout = std::io::stdout();
+                serde_json::to_writer(&mut stdout, &record)
+                    .expect("Could not write command record to stdout.");
+                writeln!(stdout).expect("Could not write command record to stdout.");
             }
-            None => (),
         }
-        Some(message)
     }
 
-    fn is_match(message: &String, pattern: &String) -> bool {
-        let re =
-            Regex::new(pattern).expect("Could not convert match pattern to regular expression.");
-        re.is_match(message)
+    fn finish(mut self) {
+        if self.options.format == OutputFormat::Text && self.options.group {
+            // Largest groups first, so outliers end up next to the prompt.
+            self.groups
+                .sort_by(|(_, a), (_, b)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+            let mut stdout = std::io::stdout();
+            for (output, mut paths) in self.groups {
+                paths.sort();
+                let header = format!(
+                    "- {} ({})\n",
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    paths.len()
+                );
+                write!(stdout, "{}\n{}", header.blue(), output)
+                    .expect("Could not write command output to stdout.");
+            }
+            return;
+        }
+
+        if self.options.format != OutputFormat::Json {
+            return;
+        }
+        let mut stdout = std::io::stdout();
+        serde_json::to_writer_pretty(&mut stdout, &self.records)
+            .expect("Could not write command records to stdout.");
+        writeln!(stdout).expect("Could not write command records to stdout.");
     }
 }
 
-impl fmt::Display for FilteredOutput {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_empty() {
-            return write!(f, "");
+#[derive(Debug, Serialize)]
+/// A repository command result, as printed by the JSON formats.
+struct CommandRecord {
+    pool: String,
+    name: String,
+    path: PathBuf,
+    argv: Vec<String>,
+    skipped: bool,
+    exit_code: Option<i32>,
+    /// Reason the command could not be started.
+    error: Option<String>,
+    /// Reason the command was killed before completing.
+    killed: Option<KillReason>,
+    stdout: String,
+    stderr: String,
+    /// Duration of the command, in seconds.
+    duration: f64,
+    /// Whether the output matched the filters.
+    matched: bool,
+}
+
+impl CommandRecord {
+    fn new(command_output: &CommandOutput, matched: bool) -> Self {
+        let (exit_code, error, stdout, stderr) = match &command_output.output {
+            None => (None, None, String::new(), String::new()),
+            Some(Err(err)) => (None, Some(err.to_string()), String::new(), String::new()),
+            Some(Ok(output)) => (
+                output.status.code(),
+                None,
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ),
+        };
+        CommandRecord {
+            pool: command_output.pool.clone(),
+            name: command_output.name.clone(),
+            path: command_output.path.clone(),
+            argv: command_output.argv.clone(),
+            skipped: command_output.output.is_none(),
+            exit_code,
+            error,
+            killed: command_output.killed,
+            stdout,
+            stderr,
+            duration: command_output.duration.as_secs_f64(),
+            matched,
         }
+    }
+}
 
-        let mut message = String::from("");
+#[derive(Debug)]
+struct Variable {
+    placeholder: &'static str,
+    env: &'static str,
+    value: String,
+}
 
-        if let Some(out) = &self.out {
-            message.push_str(&format!("{}\n", out.trim()));
+impl Variable {
+    fn new(placeholder: &'static str, env: &'static str, value: String) -> Self {
+        Variable {
+            placeholder,
+            env,
+            value,
         }
+    }
+}
 
-        if let Some(err) = &self.err {
-            message.push_str(&format!("{}\n", err.red().trim()));
-        }
+#[derive(Debug, Default)]
+/// Outcome of a command forwarded to every repository.
+pub struct CallSummary {
+    /// Number of repositories the command ran on.
+    pub count: usize,
+    /// Repositories on which the command failed.
+    pub failures: Vec<CallFailure>,
+    /// Repositories on which the command did not run, after a failure
+    /// or when skipped by the user.
+    pub skipped: Vec<PathBuf>,
+    /// Whether the command was interrupted by the user.
+    pub interrupted: bool,
+}
+
+impl CallSummary {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn record(&mut self, command_output: &CommandOutput) {
+        let output = match &command_output.output {
+            None => {
+                self.skipped.push(command_output.display_path.clone());
+                return;
+            }
+            Some(output) => output,
+        };
+
+        self.count += 1;
+
+        let reason = match (output, command_output.killed) {
+            (_, Some(KillReason::Timeout)) => {
+                format!(
+                    "timed out after {:.1}s",
+                    command_output.duration.as_secs_f64()
+                )
+            }
+            (_, Some(KillReason::Interrupt)) => String::from("interrupted"),
+            (Ok(output), None) if output.status.success() => return,
+            (Ok(output), None) => match output.status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => String::from("terminated by a signal"),
+            },
+            (Err(err), None) => format!("could not be started: {}", err),
+        };
+
+        self.failures.push(CallFailure {
+            path: command_output.display_path.clone(),
+            reason,
+        });
+    }
+}
+
+#[derive(Debug)]
+/// A repository on which a forwarded command failed.
+pub struct CallFailure {
+    /// Repository path, relative to the pool root and prefixed
+    /// with the pool label when the command ran on several pools.
+    pub path: PathBuf,
+    /// Reason of the failure.
+    pub reason: String,
+}
+
+impl fmt::Display for CallFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Vec<Variable> {
+        vec![
+            Variable::new("name", "GMUX_REPO_NAME", String::from("it's a repo")),
+            Variable::new("path", "GMUX_REPO_PATH", String::from("dir/$(rm -rf x)")),
+        ]
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(
+            GitCaller::expand("{name} {path} @{u} {unknown}", &variables(), false),
+            "it's a repo dir/$(rm -rf x) @{u} {unknown}"
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn quotes_placeholders_in_shell_lines() {
+        let line = GitCaller::expand("echo {name} {path}", &variables(), true);
+        assert_eq!(line, "echo 'it'\\''s a repo' 'dir/$(rm -rf x)'");
 
-        write!(f, "{}", message)
+        let output = Command::new("sh").arg("-c").arg(&line).output().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "it's a repo dir/$(rm -rf x)\n"
+        );
     }
 }
@@ -1,14 +1,40 @@
+mod branch;
 mod checkout;
+mod color;
+mod credentials;
 mod discovery;
 mod exceptions;
+mod fetch;
+mod filter;
 mod git;
 mod pool;
+mod query;
 mod repository;
+mod selection;
 mod settings;
+mod state;
+mod status;
+mod sync;
+mod supervisor;
+mod throttle;
+mod topic;
+pub use self::branch::*;
 pub use self::checkout::*;
+pub use self::color::*;
+pub use self::credentials::*;
 pub use self::discovery::*;
 pub use self::exceptions::*;
+pub use self::fetch::*;
+pub use self::filter::*;
 pub use self::git::*;
 pub use self::pool::*;
+pub use self::query::*;
 pub use self::repository::*;
+pub use self::selection::*;
 pub use self::settings::*;
+pub use self::state::*;
+pub use self::status::*;
+pub use self::supervisor::KillReason;
+pub use self::sync::*;
+pub use self::throttle::*;
+pub use self::topic::*;
@@ -1,22 +1,227 @@
 use clap::Parser;
-use gmux::{CheckoutManager, DiscoveryManager, GitCaller, Pool, Settings};
-use log::{debug, error, info};
+use gmux::{
+    BranchAction, BranchChange, BranchManager, BranchOutcome, CallOptions, CallSummary,
+    CheckoutManager, ColorChoice, DiscoveryManager, Error, FetchManager, FilterStream, Filters,
+    GitCaller, OutputFormat, OutputOrder, Pool, Query, RepositoryCommand, RepositoryFetch,
+    RepositorySync, Selection, Settings, StatusManager, SyncAction, SyncManager, TopicManager,
+};
+use log::{debug, error, info, warn};
 use std::io::prelude::*;
+use std::time::Duration;
 
 mod app;
-use app::{Application, ApplicationCommands, GitCommand, PoolCommands};
+use app::{
+    Application, ApplicationCommands, BranchCommands, CallArgs, Color, FilterOn, Format,
+    GitCommand, Order, PoolCommands, SelectionArgs,
+};
 
 fn report_error(err: Box<dyn std::error::Error>) {
     debug!("{:#?}", err.source());
     error!("{}", err);
 }
 
+fn exit_error(err: Box<dyn std::error::Error>) -> ! {
+    report_error(err);
+    std::process::exit(1);
+}
+
+fn report_call_summary(summary: &CallSummary) {
+    for failure in &summary.failures {
+        error!("Command failed in {}.", failure);
+    }
+    if !summary.skipped.is_empty() {
+        warn!("Command skipped in {} repositories.", summary.skipped.len());
+    }
+    error!(
+        "Command failed in {} of {} repositories.",
+        summary.failures.len(),
+        summary.count
+    );
+}
+
+fn report_progress(action: &str, repository: &str, completed: usize, total: usize) {
+    if !atty::is(atty::Stream::Stderr) {
+        return;
+    }
+    eprint!(
+        "\r\x1b[K{} {}/{} repositories, {}",
+        action, completed, total, repository
+    );
+    if completed == total {
+        eprint!("\r\x1b[K");
+    }
+}
+
+fn report_fetches(fetches: &[RepositoryFetch]) {
+    for fetch in fetches {
+        if let Some(err) = &fetch.error {
+            error!("Failed to fetch {}: {}", fetch.repository, err);
+        }
+        for remote in &fetch.remotes {
+            match &remote.error {
+                Some(err) => error!(
+                    "Failed to fetch {} from {}: {}",
+                    fetch.repository, remote.remote, err
+                ),
+                None if remote.is_updated() => info!("Fetched {} {}.", fetch.repository, remote),
+                None => debug!("Fetched {} {}.", fetch.repository, remote),
+            }
+        }
+    }
+    info!(
+        "Fetched {} repositories, {} updated, {} failed.",
+        fetches.len(),
+        fetches.iter().filter(|fetch| fetch.is_updated()).count(),
+        fetches.iter().filter(|fetch| !fetch.is_ok()).count()
+    );
+}
+
+fn report_syncs(syncs: &[RepositorySync]) {
+    for sync in syncs {
+        match &sync.action {
+            SyncAction::UpToDate => debug!("Synchronized {}, {}.", sync.repository, sync.action),
+            SyncAction::FastForwarded { .. } | SyncAction::Rebased { .. } => {
+                info!("Synchronized {}, {}.", sync.repository, sync.action)
+            }
+            SyncAction::Skipped(_) => warn!("Synchronized {}, {}.", sync.repository, sync.action),
+            SyncAction::Failed(_) => error!("Synchronized {}, {}.", sync.repository, sync.action),
+        }
+    }
+    let count = |matches: fn(&SyncAction) -> bool| {
+        syncs.iter().filter(|sync| matches(&sync.action)).count()
+    };
+    info!(
+        "Synchronized {} repositories, {} updated, {} skipped, {} failed.",
+        syncs.len(),
+        count(|action| matches!(
+            action,
+            SyncAction::FastForwarded { .. } | SyncAction::Rebased { .. }
+        )),
+        count(|action| matches!(action, SyncAction::Skipped(_))),
+        count(|action| matches!(action, SyncAction::Failed(_)))
+    );
+}
+
+fn report_branch_changes(name: &str, changes: &[BranchChange]) {
+    for change in changes {
+        match &change.outcome {
+            BranchOutcome::Missing
+            | BranchOutcome::Skipped(_)
+            | BranchOutcome::PartlyDeleted { .. } => {
+                warn!(
+                    "Branch {} in {}: {}.",
+                    name, change.repository, change.outcome
+                )
+            }
+            BranchOutcome::Failed(_) => {
+                error!(
+                    "Branch {} in {}: {}.",
+                    name, change.repository, change.outcome
+                )
+            }
+            _ => info!(
+                "Branch {} in {}: {}.",
+                name, change.repository, change.outcome
+            ),
+        }
+    }
+    if changes
+        .iter()
+        .any(|change| matches!(change.outcome, BranchOutcome::Failed(_)))
+    {
+        std::process::exit(1);
+    }
+}
+
+fn selection(selection: SelectionArgs) -> gmux::Result<Selection> {
+    Ok(Selection {
+        tags: selection.tag,
+        exclude_tags: selection.exclude_tag,
+        query: selection.where_.as_deref().map(Query::parse).transpose()?,
+    })
+}
+
+fn call_options(call: CallArgs) -> gmux::Result<CallOptions> {
+    let stream = match call.filter_on {
+        FilterOn::Both => FilterStream::Both,
+        FilterOn::Stdout => FilterStream::Stdout,
+        FilterOn::Stderr => FilterStream::Stderr,
+    };
+    let filters = Filters::new(&call.filter, &call.exclude_filter, stream, call.grep_lines)?;
+    let timeout = match call.timeout {
+        Some(timeout) if timeout > 0.0 => Some(
+            Duration::try_from_secs_f64(timeout).map_err(|_| Error::InvalidTimeout { timeout })?,
+        ),
+        Some(timeout) => return Err(Error::InvalidTimeout { timeout }.into()),
+        None => None,
+    };
+
+    Ok(CallOptions {
+        selection: selection(call.selection)?,
+        filters,
+        order: match call.order {
+            Some(Order::Path) => OutputOrder::Path,
+            Some(Order::Completion) => OutputOrder::Completion,
+            None => OutputOrder::default(),
+        },
+        format: match call.format {
+            Format::Text => OutputFormat::Text,
+            Format::Json => OutputFormat::Json,
+            Format::Ndjson => OutputFormat::Ndjson,
+        },
+        fail_fast: call.fail_fast,
+        jobs: call.jobs,
+        host_jobs: call.host_jobs,
+        interactive: call.interactive,
+        group: call.group,
+        timeout,
+    })
+}
+
+fn run_call(settings: &Settings, command: RepositoryCommand, call: CallArgs, all_pools: bool) {
+    let options = match call_options(call) {
+        Ok(options) => options,
+        Err(err) => exit_error(err),
+    };
+    let git = if all_pools {
+        GitCaller::all_pools(settings)
+    } else {
+        GitCaller::new(settings)
+    };
+    let git = match git {
+        Ok(git) => git,
+        Err(err) => exit_error(err),
+    };
+    match git.call(&command, &options) {
+        Ok(summary) => {
+            if !summary.is_success() {
+                report_call_summary(&summary);
+            }
+            if summary.interrupted {
+                std::process::exit(130);
+            }
+            if !summary.is_success() {
+                std::process::exit(1);
+            }
+        }
+        Err(err) => exit_error(err),
+    }
+}
+
 fn main() {
     let app = Application::parse();
     let mut settings = Settings::load().map_err(report_error).unwrap();
+    settings.selected = app.pool;
 
     pretty_env_logger::init();
 
+    match app.color {
+        Color::Auto => ColorChoice::Auto,
+        Color::Always => ColorChoice::Always,
+        Color::Never => ColorChoice::Never,
+    }
+    .apply();
+
     match app.command {
         ApplicationCommands::Pool { pool_command } => match pool_command {
             None => match Pool::from_current(&settings) {
@@ -85,12 +290,88 @@ fn main() {
                     },
                     Err(err) => report_error(err),
                 },
-                PoolCommands::Checkout => match CheckoutManager::checkout(&mut settings) {
-                    Ok(checkout) => info!(
-                        "Checked out {} branches and {} repositories.",
-                        checkout.branches.len(),
-                        checkout.clone.len()
-                    ),
+                PoolCommands::Checkout {
+                    jobs,
+                    autostash,
+                    selection: selection_args,
+                } => {
+                    let selection = match selection(selection_args) {
+                        Ok(selection) => selection,
+                        Err(err) => exit_error(err),
+                    };
+                    match CheckoutManager::checkout(&mut settings, jobs, autostash, &selection) {
+                        Ok(checkout) => {
+                            for clone in &checkout.clone {
+                                match &clone.error {
+                                    None if clone.missing_branch => warn!(
+                                        "Cloned {} on its default branch, {} does not exist on {}.",
+                                        clone.repository,
+                                        clone.repository.branch_name(),
+                                        clone.repository.fetch.name
+                                    ),
+                                    None => info!("Cloned {}.", clone.repository),
+                                    Some(err) => {
+                                        error!("Failed to clone {}: {}", clone.repository, err)
+                                    }
+                                }
+                            }
+                            for branch in &checkout.branches {
+                                let actions = branch
+                                    .actions
+                                    .iter()
+                                    .map(|action| action.to_string())
+                                    .collect::<Vec<String>>()
+                                    .join(", ");
+                                match &branch.error {
+                                    None => info!(
+                                        "Checked out {} in {} ({}).",
+                                        branch.next, branch.repository, actions
+                                    ),
+                                    Some(err) => error!(
+                                        "Failed to check out {} in {}: {}",
+                                        branch.next, branch.repository, err
+                                    ),
+                                }
+                                if branch.actions.contains(&BranchAction::StashConflict) {
+                                    warn!(
+                                        "Stashed changes of {} conflict with {}, they are kept in the stash.",
+                                        branch.repository, branch.next
+                                    );
+                                }
+                            }
+                            info!(
+                                "Checked out {} branches and {} repositories.",
+                                checkout
+                                    .branches
+                                    .iter()
+                                    .filter(|branch| branch.is_ok())
+                                    .count(),
+                                checkout.clone.iter().filter(|clone| clone.is_ok()).count()
+                            );
+                            if !checkout.is_ok() {
+                                std::process::exit(1);
+                            }
+                        }
+                        Err(err) => exit_error(err),
+                    }
+                }
+                PoolCommands::Tag {
+                    repository,
+                    tag,
+                    remove,
+                } => match Pool::from_current(&settings) {
+                    Ok(mut pool) => {
+                        let tagged = if remove {
+                            pool.remove_tag(&settings, &repository, &tag)
+                        } else {
+                            pool.add_tag(&settings, &repository, tag.clone())
+                        };
+                        match tagged {
+                            Ok(_) if remove => info!("Removed tag {} from {}.", tag, repository),
+                            Ok(_) => info!("Tagged {} with {}.", repository, tag),
+                            Err(err) => report_error(err),
+                        }
+                    }
                     Err(err) => report_error(err),
                 },
                 PoolCommands::Discover => match DiscoveryManager::discover_current(&settings) {
@@ -100,19 +381,184 @@ fn main() {
             },
         },
         ApplicationCommands::Command {
-            exclude_filter,
-            filter,
+            call,
+            all_pools,
             command,
         } => match command {
-            GitCommand::Command(args) => match GitCaller::new(&settings) {
-                Ok(git) => {
-                    git.call(args, filter, exclude_filter).unwrap();
-                    ()
-                }
-                Err(err) => {
-                    report_error(err);
-                }
+            GitCommand::Command(args) => {
+                run_call(&settings, RepositoryCommand::Git(args), call, all_pools)
+            }
+        },
+        ApplicationCommands::Branch { branch_command } => match branch_command {
+            BranchCommands::Create {
+                name,
+                from,
+                push,
+                selection: selection_args,
+                jobs,
+            } => match selection(selection_args).and_then(|selection| {
+                BranchManager::create(&settings, jobs, &selection, &name, from.as_deref(), push)
+            }) {
+                Ok(changes) => report_branch_changes(&name, &changes),
+                Err(err) => exit_error(err),
+            },
+            BranchCommands::Switch {
+                name,
+                autostash,
+                selection: selection_args,
+                jobs,
+            } => match selection(selection_args).and_then(|selection| {
+                BranchManager::switch(&settings, jobs, &selection, &name, autostash)
+            }) {
+                Ok(changes) => report_branch_changes(&name, &changes),
+                Err(err) => exit_error(err),
+            },
+            BranchCommands::Delete {
+                name,
+                force,
+                push,
+                selection: selection_args,
+                jobs,
+            } => match selection(selection_args).and_then(|selection| {
+                BranchManager::delete(&settings, jobs, &selection, &name, force, push)
+            }) {
+                Ok(changes) => report_branch_changes(&name, &changes),
+                Err(err) => exit_error(err),
             },
         },
+        ApplicationCommands::Topic {
+            name,
+            cleanup,
+            selection: selection_args,
+            jobs,
+            json,
+        } => {
+            let selection = match selection(selection_args) {
+                Ok(selection) => selection,
+                Err(err) => exit_error(err),
+            };
+            if cleanup {
+                return match TopicManager::cleanup(&settings, jobs, &selection, &name) {
+                    Ok(changes) => report_branch_changes(&name, &changes),
+                    Err(err) => exit_error(err),
+                };
+            }
+            match TopicManager::topic(&settings, jobs, &selection, &name) {
+                Ok(topics) => {
+                    let output = if json {
+                        match serde_json::to_string_pretty(&topics) {
+                            Ok(output) => format!("{}\n", output),
+                            Err(err) => return report_error(err.into()),
+                        }
+                    } else {
+                        TopicManager::table(&topics)
+                    };
+                    if let Err(err) = std::io::stdout().write_all(output.as_bytes()) {
+                        report_error(err.into())
+                    }
+                }
+                Err(err) => exit_error(err),
+            }
+        }
+        ApplicationCommands::Status {
+            selection: selection_args,
+            jobs,
+            json,
+        } => {
+            let selection = match selection(selection_args) {
+                Ok(selection) => selection,
+                Err(err) => exit_error(err),
+            };
+            match StatusManager::status(&settings, jobs, &selection) {
+                Ok(statuses) => {
+                    for status in &statuses {
+                        if let Some(err) = &status.error {
+                            error!("Could not read {}: {}", status.path.display(), err);
+                        }
+                    }
+                    let output = if json {
+                        match serde_json::to_string_pretty(&statuses) {
+                            Ok(output) => format!("{}\n", output),
+                            Err(err) => return report_error(err.into()),
+                        }
+                    } else {
+                        StatusManager::table(&statuses)
+                    };
+                    if let Err(err) = std::io::stdout().write_all(output.as_bytes()) {
+                        report_error(err.into())
+                    }
+                }
+                Err(err) => exit_error(err),
+            }
+        }
+        ApplicationCommands::Fetch {
+            selection: selection_args,
+            jobs,
+            no_prune,
+        } => {
+            let selection = match selection(selection_args) {
+                Ok(selection) => selection,
+                Err(err) => exit_error(err),
+            };
+            match FetchManager::fetch(
+                &settings,
+                jobs,
+                &selection,
+                !no_prune,
+                &|fetch, completed, total| {
+                    report_progress("Fetched", &fetch.repository, completed, total)
+                },
+            ) {
+                Ok(fetches) => {
+                    report_fetches(&fetches);
+                    if fetches.iter().any(|fetch| !fetch.is_ok()) {
+                        std::process::exit(1);
+                    }
+                }
+                Err(err) => exit_error(err),
+            }
+        }
+        ApplicationCommands::Sync {
+            selection: selection_args,
+            jobs,
+            rebase,
+        } => {
+            let selection = match selection(selection_args) {
+                Ok(selection) => selection,
+                Err(err) => exit_error(err),
+            };
+            match SyncManager::sync(
+                &settings,
+                jobs,
+                &selection,
+                rebase,
+                &|sync, completed, total| {
+                    report_progress("Synchronized", &sync.repository, completed, total)
+                },
+            ) {
+                Ok(syncs) => {
+                    report_syncs(&syncs);
+                    if syncs
+                        .iter()
+                        .any(|sync| matches!(sync.action, SyncAction::Failed(_)))
+                    {
+                        std::process::exit(1);
+                    }
+                }
+                Err(err) => exit_error(err),
+            }
+        }
+        ApplicationCommands::Exec {
+            call,
+            shell,
+            command,
+        } => {
+            let command = if shell {
+                RepositoryCommand::Shell(command.join(" "))
+            } else {
+                RepositoryCommand::Exec(command)
+            };
+            run_call(&settings, command, call, false)
+        }
     }
 }
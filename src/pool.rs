@@ -1,4 +1,4 @@
-use super::{Error, File, Repository, Result, Settings};
+use super::{Error, File, HostThrottle, Repository, Result, Settings};
 use glob::Pattern;
 use serde::{Deserialize, Serialize};
 use serde_yaml::{from_reader, to_writer};
@@ -23,6 +23,12 @@ pub struct Pool {
     pub repositories: Vec<Repository>,
     /// List of files managed by this pool.
     pub files: Vec<File>,
+    /// Number of parallel workers, overrides `Settings::jobs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Number of parallel workers per remote host, overrides `Settings::host_jobs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_jobs: Option<usize>,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub exclude_patterns: Vec<Pattern>,
@@ -56,7 +62,7 @@ impl Pool {
                     .to_str()
                     .expect("Could not parse label"),
             );
-            let pool = Pool::from_label(&settings, label)?;
+            let pool = Pool::from_label(settings, label)?;
             pools.push(pool);
         }
 
@@ -82,6 +88,8 @@ impl Pool {
             excludes,
             repositories,
             files,
+            jobs: None,
+            host_jobs: None,
             exclude_patterns,
         };
 
@@ -105,21 +113,36 @@ impl Pool {
         let exclude_patterns = pool
             .excludes
             .iter()
-            .map(|pattern| Pattern::new(&pattern).expect("Invalid exclude patterns"))
+            .map(|pattern| Pattern::new(pattern).expect("Invalid exclude patterns"))
             .collect();
 
         pool.exclude_patterns = exclude_patterns;
 
-        return Ok(pool);
+        Ok(pool)
     }
 
     pub fn from_current(settings: &Settings) -> Result<Self> {
-        if let Some(label) = &settings.current {
+        if let Some(label) = settings.selected.as_ref().or(settings.current.as_ref()) {
             return Pool::from_label(settings, String::from(label));
         }
         Err(Error::NoCurrentPoolSet.into())
     }
 
+    /// Worker threads and per host throttle running commands on this pool.
+    ///
+    /// `jobs` overrides the pool value, itself overriding the settings one.
+    pub(crate) fn workers(
+        &self,
+        settings: &Settings,
+        jobs: Option<usize>,
+    ) -> Result<(rayon::ThreadPool, HostThrottle)> {
+        let workers = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.or(self.jobs).or(settings.jobs).unwrap_or(0))
+            .build()?;
+        let throttle = HostThrottle::new(self.host_jobs.or(settings.host_jobs));
+        Ok((workers, throttle))
+    }
+
     pub fn set_as_current(&self, settings: &mut Settings) -> Result<()> {
         settings.current = Some(self.label.clone());
         settings.save()?;
@@ -136,7 +159,7 @@ impl Pool {
     }
 
     pub fn set_label(&mut self, settings: &Settings, label: String) -> Result<()> {
-        let existing = Pool::from_label(&settings, label.clone());
+        let existing = Pool::from_label(settings, label.clone());
         if existing.is_ok() {
             return Err(Error::PoolAlreadyExists { label }.into());
         }
@@ -157,13 +180,57 @@ impl Pool {
         Ok(())
     }
 
+    /// Find a repository by its path relative to the root, or by its name if unique.
+    pub fn find_repository(&mut self, repository: &str) -> Result<&mut Repository> {
+        let mut matches: Vec<usize> = (0..self.repositories.len())
+            .filter(|index| self.repositories[*index].to_string() == repository)
+            .collect();
+        if matches.is_empty() {
+            matches = (0..self.repositories.len())
+                .filter(|index| self.repositories[*index].name == repository)
+                .collect();
+        }
+
+        match matches.as_slice() {
+            [index] => Ok(&mut self.repositories[*index]),
+            [] => Err(Error::RepositoryNotInPool {
+                repository: String::from(repository),
+            }
+            .into()),
+            _ => Err(Error::AmbiguousRepository {
+                repository: String::from(repository),
+                matches: matches
+                    .iter()
+                    .map(|index| self.repositories[*index].to_string())
+                    .collect(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn add_tag(&mut self, settings: &Settings, repository: &str, tag: String) -> Result<()> {
+        let repository = self.find_repository(repository)?;
+        if !repository.tags.contains(&tag) {
+            repository.tags.push(tag);
+        }
+        self.save(settings)?;
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, settings: &Settings, repository: &str, tag: &str) -> Result<()> {
+        let repository = self.find_repository(repository)?;
+        repository.tags.retain(|other| other != tag);
+        self.save(settings)?;
+        Ok(())
+    }
+
     pub fn save(&self, settings: &Settings) -> Result<()> {
         let writer = std::fs::File::create(Pool::path(settings, &self.label))?;
 
         to_writer(writer, &self)?;
         Ok(())
     }
-    fn path(settings: &Settings, label: &String) -> PathBuf {
+    fn path(settings: &Settings, label: &str) -> PathBuf {
         settings.store_full_path().join(label).with_extension("yml")
     }
 }
@@ -0,0 +1,558 @@
+use super::{Error, Repository, RepositoryState, Result};
+use regex::Regex;
+
+const FIELDS: [&str; 10] = [
+    "name",
+    "path",
+    "branch",
+    "remote",
+    "head",
+    "dirty",
+    "ahead",
+    "behind",
+    "detached",
+    "has_stash",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Path,
+    Branch,
+    Remote,
+    Head,
+    Dirty,
+    Ahead,
+    Behind,
+    Detached,
+    HasStash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Number,
+    Bool,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name {
+            "name" => Some(Field::Name),
+            "path" => Some(Field::Path),
+            "branch" => Some(Field::Branch),
+            "remote" => Some(Field::Remote),
+            "head" => Some(Field::Head),
+            "dirty" => Some(Field::Dirty),
+            "ahead" => Some(Field::Ahead),
+            "behind" => Some(Field::Behind),
+            "detached" => Some(Field::Detached),
+            "has_stash" => Some(Field::HasStash),
+            _ => None,
+        }
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            Field::Name | Field::Path | Field::Branch | Field::Remote | Field::Head => Kind::Text,
+            Field::Ahead | Field::Behind => Kind::Number,
+            Field::Dirty | Field::Detached | Field::HasStash => Kind::Bool,
+        }
+    }
+
+    /// Whether the field is read from the working copy instead of the pool.
+    fn is_live(self) -> bool {
+        !matches!(
+            self,
+            Field::Name | Field::Path | Field::Branch | Field::Remote
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Lower,
+    LowerOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Match,
+    NotMatch,
+}
+
+impl Operator {
+    fn parse(symbol: &str) -> Option<Operator> {
+        match symbol {
+            "==" | "=" => Some(Operator::Equal),
+            "!=" => Some(Operator::NotEqual),
+            "<" => Some(Operator::Lower),
+            "<=" => Some(Operator::LowerOrEqual),
+            ">" => Some(Operator::Greater),
+            ">=" => Some(Operator::GreaterOrEqual),
+            "~" => Some(Operator::Match),
+            "!~" => Some(Operator::NotMatch),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Number(usize),
+    Bool(bool),
+    Pattern(Regex),
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// A boolean field on its own.
+    Flag(Field),
+    Compare(Field, Operator, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+/// Symbols, longest first so `!=` is not read as `!` followed by `=`.
+const SYMBOLS: [&str; 14] = [
+    "==", "!=", "<=", ">=", "!~", "&&", "||", "<", ">", "=", "~", "!", "(", ")",
+];
+
+#[derive(Debug, Clone)]
+/// An expression selecting repositories, see `gmux command --help`.
+///
+/// Fields of the pool model (`name`, `path`, `branch`, `remote`) are compared
+/// with the live state of the working copy (`head`, `dirty`, `ahead`, `behind`,
+/// `detached`, `has_stash`), for instance `head != main and dirty`.
+pub struct Query {
+    expression: Expression,
+}
+
+impl Query {
+    pub fn parse(source: &str) -> Result<Self> {
+        let invalid = |reason: String| -> Box<dyn std::error::Error> {
+            Error::InvalidQuery {
+                query: String::from(source),
+                reason,
+            }
+            .into()
+        };
+
+        let tokens = Query::tokenize(source).map_err(invalid)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.or().map_err(invalid)?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("unexpected {}", describe(token))));
+        }
+
+        Ok(Query { expression })
+    }
+
+    /// Whether evaluating the query requires the working copy state.
+    pub fn needs_state(&self) -> bool {
+        Query::is_live(&self.expression)
+    }
+
+    /// Evaluate the query, live fields of missing working copies
+    /// being empty, zero or false.
+    pub fn matches(&self, repository: &Repository, state: Option<&RepositoryState>) -> bool {
+        let default = RepositoryState::default();
+        Query::evaluate(&self.expression, repository, state.unwrap_or(&default))
+    }
+
+    fn is_live(expression: &Expression) -> bool {
+        match expression {
+            Expression::And(left, right) | Expression::Or(left, right) => {
+                Query::is_live(left) || Query::is_live(right)
+            }
+            Expression::Not(expression) => Query::is_live(expression),
+            Expression::Flag(field) | Expression::Compare(field, _, _) => field.is_live(),
+        }
+    }
+
+    fn evaluate(expression: &Expression, repository: &Repository, state: &RepositoryState) -> bool {
+        match expression {
+            Expression::And(left, right) => {
+                Query::evaluate(left, repository, state)
+                    && Query::evaluate(right, repository, state)
+            }
+            Expression::Or(left, right) => {
+                Query::evaluate(left, repository, state)
+                    || Query::evaluate(right, repository, state)
+            }
+            Expression::Not(expression) => !Query::evaluate(expression, repository, state),
+            Expression::Flag(field) => Query::flag(*field, state),
+            Expression::Compare(field, operator, value) => {
+                Query::compare(*field, *operator, value, repository, state)
+            }
+        }
+    }
+
+    fn flag(field: Field, state: &RepositoryState) -> bool {
+        match field {
+            Field::Dirty => state.dirty,
+            Field::Detached => state.detached,
+            Field::HasStash => state.stashes > 0,
+            _ => false,
+        }
+    }
+
+    fn compare(
+        field: Field,
+        operator: Operator,
+        value: &Value,
+        repository: &Repository,
+        state: &RepositoryState,
+    ) -> bool {
+        match value {
+            Value::Text(value) => {
+                let text = Query::text(field, repository, state);
+                match operator {
+                    Operator::Equal => text == *value,
+                    Operator::NotEqual => text != *value,
+                    _ => false,
+                }
+            }
+            Value::Pattern(pattern) => {
+                let text = Query::text(field, repository, state);
+                match operator {
+                    Operator::Match => pattern.is_match(&text),
+                    Operator::NotMatch => !pattern.is_match(&text),
+                    _ => false,
+                }
+            }
+            Value::Number(value) => {
+                let number = match field {
+                    Field::Ahead => state.ahead,
+                    Field::Behind => state.behind,
+                    _ => return false,
+                };
+                match operator {
+                    Operator::Equal => number == *value,
+                    Operator::NotEqual => number != *value,
+                    Operator::Lower => number < *value,
+                    Operator::LowerOrEqual => number <= *value,
+                    Operator::Greater => number > *value,
+                    Operator::GreaterOrEqual => number >= *value,
+                    _ => false,
+                }
+            }
+            Value::Bool(value) => match operator {
+                Operator::Equal => Query::flag(field, state) == *value,
+                Operator::NotEqual => Query::flag(field, state) != *value,
+                _ => false,
+            },
+        }
+    }
+
+    fn text(field: Field, repository: &Repository, state: &RepositoryState) -> String {
+        match field {
+            Field::Name => repository.name.clone(),
+            Field::Path => repository.to_string(),
+            Field::Branch => String::from(repository.branch_name()),
+            Field::Remote => repository.fetch.url.clone(),
+            Field::Head => state.head.clone().unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    fn tokenize(source: &str) -> std::result::Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        let mut rest = source.trim_start();
+
+        while !rest.is_empty() {
+            if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                tokens.push(Token::Symbol(symbol));
+                rest = &rest[symbol.len()..];
+            } else if rest.starts_with('"') || rest.starts_with('\'') {
+                let quote = &rest[..1];
+                let end = rest[1..]
+                    .find(quote)
+                    .ok_or_else(|| String::from("unterminated string"))?;
+                tokens.push(Token::Quoted(String::from(&rest[1..end + 1])));
+                rest = &rest[end + 2..];
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "=!<>~&|()'\"".contains(c))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    // A lone `&` or `|`, the other symbols are matched above.
+                    let unexpected = rest.chars().next().unwrap_or_default();
+                    return Err(format!("unexpected '{}'", unexpected));
+                }
+                tokens.push(Token::Word(String::from(&rest[..end])));
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start();
+        }
+
+        Ok(tokens)
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("'{}'", word),
+        Token::Quoted(text) => format!("\"{}\"", text),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+}
+
+/// Recursive descent parser, `not` binding tighter than `and`, itself tighter than `or`.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+type ParseResult = std::result::Result<Expression, String>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consume the next token if it is one of the given keywords or symbols.
+    fn accept(&mut self, keyword: &str, symbol: &str) -> bool {
+        let accepted = match self.peek() {
+            Some(Token::Word(word)) => word == keyword,
+            Some(Token::Symbol(other)) => *other == symbol,
+            _ => false,
+        };
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn or(&mut self) -> ParseResult {
+        let mut expression = self.and()?;
+        while self.accept("or", "||") {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> ParseResult {
+        let mut expression = self.not()?;
+        while self.accept("and", "&&") {
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> ParseResult {
+        if self.accept("not", "!") {
+            return Ok(Expression::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> ParseResult {
+        let name = match self.next() {
+            Some(Token::Symbol("(")) => {
+                let expression = self.or()?;
+                return match self.next() {
+                    Some(Token::Symbol(")")) => Ok(expression),
+                    Some(token) => Err(format!("expected ')', found {}", describe(&token))),
+                    None => Err(String::from("expected ')'")),
+                };
+            }
+            Some(Token::Word(name)) => name,
+            Some(token) => return Err(format!("expected a field, found {}", describe(&token))),
+            None => return Err(String::from("expected a field")),
+        };
+
+        let field = Field::parse(&name).ok_or_else(|| {
+            format!(
+                "unknown field '{}', expected one of {}",
+                name,
+                FIELDS.join(", ")
+            )
+        })?;
+
+        let operator = match self.peek() {
+            Some(Token::Symbol(symbol)) => Operator::parse(symbol),
+            _ => None,
+        };
+        let operator = match operator {
+            Some(operator) => {
+                self.position += 1;
+                operator
+            }
+            None if field.kind() == Kind::Bool => return Ok(Expression::Flag(field)),
+            None => return Err(format!("field '{}' must be compared to a value", name)),
+        };
+
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            Some(token) => return Err(format!("expected a value, found {}", describe(&token))),
+            None => return Err(format!("expected a value after '{}'", name)),
+        };
+
+        let value = match (field.kind(), operator) {
+            (Kind::Text, Operator::Equal | Operator::NotEqual) => Value::Text(value),
+            (Kind::Text, Operator::Match | Operator::NotMatch) => Value::Pattern(
+                Regex::new(&value)
+                    .map_err(|err| format!("invalid pattern '{}': {}", value, err))?,
+            ),
+            (Kind::Number, Operator::Match | Operator::NotMatch) => {
+                return Err(format!(
+                    "field '{}' is a number, it cannot match a pattern",
+                    name
+                ))
+            }
+            (Kind::Number, _) => Value::Number(
+                value
+                    .parse()
+                    .map_err(|_| format!("field '{}' must be compared to a number", name))?,
+            ),
+            (Kind::Bool, Operator::Equal | Operator::NotEqual) => match value.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => {
+                    return Err(format!(
+                        "field '{}' must be compared to true or false",
+                        name
+                    ))
+                }
+            },
+            _ => return Err(format!("field '{}' does not support this operator", name)),
+        };
+
+        Ok(Expression::Compare(field, operator, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Remote;
+    use std::path::PathBuf;
+
+    fn repository() -> Repository {
+        let fetch = Remote {
+            name: String::from("origin"),
+            url: String::from("git@example.com:team/api.git"),
+        };
+        Repository {
+            name: String::from("api"),
+            path: PathBuf::from("services"),
+            branch: String::from("refs/heads/main"),
+            remotes: vec![fetch.clone()],
+            fetch,
+            tags: Vec::new(),
+        }
+    }
+
+    fn state() -> RepositoryState {
+        RepositoryState {
+            head: Some(String::from("feature")),
+            dirty: true,
+            ahead: 2,
+            ..RepositoryState::default()
+        }
+    }
+
+    fn matches(source: &str) -> bool {
+        Query::parse(source)
+            .unwrap()
+            .matches(&repository(), Some(&state()))
+    }
+
+    fn error(source: &str) -> String {
+        Query::parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_tighter_than_or() {
+        assert!(matches("detached or dirty and ahead > 1"));
+        assert!(!matches("(detached or dirty) and ahead > 2"));
+        assert!(matches("not detached and dirty"));
+        assert!(!matches("not (detached or dirty)"));
+        assert!(matches("! detached && dirty || has_stash"));
+    }
+
+    #[test]
+    fn compares_text() {
+        assert!(matches("name == api"));
+        assert!(matches("name = api and path == services/api"));
+        assert!(matches("branch == main and head != main"));
+        assert!(matches("remote == git@example.com:team/api.git"));
+        assert!(!matches("head == main"));
+    }
+
+    #[test]
+    fn reads_quoted_values() {
+        assert!(matches("name == \"api\""));
+        assert!(matches("head == 'feature'"));
+        assert!(matches("name != 'not and or'"));
+        assert!(error("name == 'api").contains("unterminated string"));
+    }
+
+    #[test]
+    fn compares_booleans() {
+        assert!(matches("dirty"));
+        assert!(matches("dirty == true and detached == false"));
+        assert!(matches("has_stash != true"));
+        assert!(error("dirty == yes").contains("must be compared to true or false"));
+        assert!(error("dirty ~ true").contains("does not support this operator"));
+    }
+
+    #[test]
+    fn compares_numbers() {
+        assert!(matches("ahead == 2 and behind == 0"));
+        assert!(matches(
+            "ahead >= 2 and ahead <= 2 and ahead < 3 and ahead > 1"
+        ));
+        assert!(!matches("behind > 0"));
+        assert!(error("ahead > two").contains("must be compared to a number"));
+        assert!(error("ahead ~ 1").contains("cannot match a pattern"));
+    }
+
+    #[test]
+    fn matches_patterns() {
+        assert!(matches("name ~ ^a"));
+        assert!(matches("remote ~ 'example\\.com'"));
+        assert!(matches("head !~ ^main$"));
+        assert!(error("name ~ '('").contains("invalid pattern"));
+    }
+
+    #[test]
+    fn missing_working_copy_has_empty_state() {
+        let query = Query::parse("not dirty and ahead == 0 and head == ''").unwrap();
+        assert!(query.needs_state());
+        assert!(query.matches(&repository(), None));
+        assert!(!Query::parse("name == api").unwrap().needs_state());
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert!(error("dirty & name == a").contains("unexpected '&'"));
+        assert!(error("dirty | name == a").contains("unexpected '|'"));
+        assert!(error("dirty and").contains("expected a field"));
+        assert!(error("(dirty").contains("expected ')'"));
+        assert!(error("dirty)").contains("unexpected ')'"));
+        assert!(error("name").contains("must be compared to a value"));
+        assert!(error("name ==").contains("expected a value"));
+        assert!(error("size > 1").contains("unknown field 'size'"));
+        assert!(error("").contains("expected a field"));
+    }
+}
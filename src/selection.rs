@@ -0,0 +1,38 @@
+use super::{Pool, Query, Repository, RepositoryState};
+
+#[derive(Debug, Clone, Default)]
+/// Criteria selecting the repositories a command applies to.
+pub struct Selection {
+    /// Keep repositories carrying any of these tags, every repository if empty.
+    pub tags: Vec<String>,
+    /// Drop repositories carrying any of these tags.
+    pub exclude_tags: Vec<String>,
+    /// Keep repositories matching this query.
+    pub query: Option<Query>,
+}
+
+impl Selection {
+    /// Whether a repository of the pool is selected.
+    ///
+    /// The working copy state is only read when the query needs it.
+    pub fn matches(&self, pool: &Pool, repository: &Repository) -> bool {
+        let has_tag = |tag: &String| repository.tags.contains(tag);
+        if self.exclude_tags.iter().any(has_tag) {
+            return false;
+        }
+        if !self.tags.is_empty() && !self.tags.iter().any(has_tag) {
+            return false;
+        }
+
+        let query = match &self.query {
+            Some(query) => query,
+            None => return true,
+        };
+        let state = if query.needs_state() {
+            RepositoryState::read(&repository.full_path(&pool.root)).ok()
+        } else {
+            None
+        };
+        query.matches(repository, state.as_ref())
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Error, Result};
+use super::{CredentialMethod, Error, Result};
 use config::{Config, FileFormat};
 use dirs::config_dir;
 use serde::{Deserialize, Serialize};
@@ -12,6 +12,19 @@ pub struct Settings {
     pub store: PathBuf,
     pub executable: PathBuf,
     pub current: Option<String>,
+    /// Pool selected for this invocation only, takes precedence over `current`
+    /// and is never saved.
+    #[serde(skip)]
+    pub selected: Option<String>,
+    /// Number of parallel workers, defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Number of parallel workers per remote host, unlimited by default.
+    pub host_jobs: Option<usize>,
+    /// Authentication methods to try against remotes, in order.
+    pub credentials: Vec<CredentialMethod>,
+    /// Private key files used by the `ssh-key` credential method.
+    /// Defaults to the usual keys of `~/.ssh` when empty.
+    pub ssh_keys: Vec<PathBuf>,
 }
 
 impl Settings {
@@ -64,27 +77,20 @@ impl Settings {
     }
 
     fn app_config_dir() -> Option<PathBuf> {
-        match env::var_os("GMUX_CONFIG_DIR") {
-            Some(path) => {
-                let config_path = PathBuf::from(path);
-                if config_path.is_dir() {
-                    return Some(config_path);
-                }
-                ()
+        if let Some(path) = env::var_os("GMUX_CONFIG_DIR") {
+            let config_path = PathBuf::from(path);
+            if config_path.is_dir() {
+                return Some(config_path);
             }
-            None => (),
-        }
-        match config_dir() {
-            Some(path) => Some(path.join("gmux")),
-            None => None,
         }
+        config_dir().map(|path| path.join("gmux"))
     }
 
     fn user_config_file() -> Option<PathBuf> {
         if let Some(path) = Settings::app_config_dir() {
             let user_settings_path = path.join("gmux.yml");
             // Ensure this is a valid OS path.
-            if let Some(_) = user_settings_path.to_str() {
+            if user_settings_path.to_str().is_some() {
                 return Some(user_settings_path);
             }
         }
@@ -0,0 +1,118 @@
+use super::Result;
+use git2::{BranchType, Status, StatusOptions};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// Live state of a repository working copy, read with `git2`.
+pub struct RepositoryState {
+    /// Short name of the current branch, `None` when HEAD is detached.
+    pub head: Option<String>,
+    /// Whether HEAD points to a commit instead of a branch.
+    pub detached: bool,
+    /// Whether tracked files have uncommitted changes.
+    pub dirty: bool,
+    /// Number of files with staged changes.
+    pub staged: usize,
+    /// Number of tracked files with unstaged changes.
+    pub unstaged: usize,
+    /// Number of untracked files and directories.
+    pub untracked: usize,
+    /// Commits of the current branch missing from its upstream.
+    pub ahead: usize,
+    /// Commits of the upstream missing from the current branch.
+    pub behind: usize,
+    /// Number of stashed changes.
+    pub stashes: usize,
+}
+
+impl RepositoryState {
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut git = git2::Repository::open(path)?;
+
+        let detached = git.head_detached()?;
+        let head = if detached {
+            None
+        } else {
+            git.head()?.shorthand().map(String::from)
+        };
+
+        let (ahead, behind) = match &head {
+            Some(name) => RepositoryState::ahead_behind(&git, name)?,
+            None => (0, 0),
+        };
+
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(false)
+            .include_ignored(false)
+            .exclude_submodules(true);
+        let (mut staged, mut unstaged, mut untracked) = (0, 0, 0);
+        for entry in git.statuses(Some(&mut options))?.iter() {
+            let status = entry.status();
+            if status.intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            ) {
+                staged += 1;
+            }
+            if status.intersects(
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE
+                    | Status::CONFLICTED,
+            ) {
+                unstaged += 1;
+            }
+            if status.contains(Status::WT_NEW) {
+                untracked += 1;
+            }
+        }
+
+        let mut stashes = 0;
+        git.stash_foreach(|_, _, _| {
+            stashes += 1;
+            true
+        })?;
+
+        Ok(RepositoryState {
+            head,
+            detached,
+            dirty: staged + unstaged > 0,
+            staged,
+            unstaged,
+            untracked,
+            ahead,
+            behind,
+            stashes,
+        })
+    }
+
+    /// Whether tracked files have uncommitted changes, untracked files are ignored.
+    pub(crate) fn is_dirty(git: &git2::Repository) -> Result<bool> {
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(false)
+            .include_ignored(false)
+            .exclude_submodules(true);
+        Ok(!git.statuses(Some(&mut options))?.is_empty())
+    }
+
+    /// Commits ahead and behind the upstream of a local branch, none without upstream.
+    fn ahead_behind(git: &git2::Repository, name: &str) -> Result<(usize, usize)> {
+        let branch = git.find_branch(name, BranchType::Local)?;
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(_) => return Ok((0, 0)),
+        };
+        match (branch.get().target(), upstream.get().target()) {
+            (Some(local), Some(upstream)) => Ok(git.graph_ahead_behind(local, upstream)?),
+            _ => Ok((0, 0)),
+        }
+    }
+}
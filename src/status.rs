@@ -0,0 +1,170 @@
+use super::{Pool, Repository, RepositoryState, Result, Selection, Settings};
+use colored::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+
+pub struct StatusManager;
+
+impl StatusManager {
+    /// Read the state of the selected repositories of the current pool, in parallel.
+    pub fn status(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+    ) -> Result<Vec<RepositoryStatus>> {
+        let pool = Pool::from_current(settings)?;
+
+        let (workers, _) = pool.workers(settings, jobs)?;
+
+        let mut statuses: Vec<RepositoryStatus> = workers.install(|| {
+            pool.repositories
+                .par_iter()
+                .filter(|repository| selection.matches(&pool, repository))
+                .map(|repository| RepositoryStatus::read(&pool, repository))
+                .collect()
+        });
+        statuses.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(statuses)
+    }
+
+    /// Render statuses as a table, one row per repository.
+    pub fn table(statuses: &[RepositoryStatus]) -> String {
+        let header = [
+            "REPOSITORY",
+            "BRANCH",
+            "RECORDED",
+            "AHEAD",
+            "BEHIND",
+            "STAGED",
+            "UNSTAGED",
+            "UNTRACKED",
+            "STASHES",
+        ];
+        let rows: Vec<(Vec<String>, bool)> = statuses
+            .iter()
+            .map(|status| (status.row(), !status.is_clean()))
+            .collect();
+        table(&header, &rows)
+    }
+}
+
+/// Render a table with aligned columns, highlighted rows in yellow.
+pub(crate) fn table(header: &[&str], rows: &[(Vec<String>, bool)]) -> String {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .map(|(row, _)| row[column].chars().count())
+                .chain(std::iter::once(header[column].len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut table = String::new();
+    let header: Vec<String> = header.iter().map(|title| String::from(*title)).collect();
+    table.push_str(&line(&header).bold().to_string());
+    table.push('\n');
+    for (row, highlighted) in rows {
+        let row = line(row);
+        let row = if *highlighted {
+            row.yellow()
+        } else {
+            row.normal()
+        };
+        table.push_str(&row.to_string());
+        table.push('\n');
+    }
+    table
+}
+
+#[derive(Debug, Serialize)]
+/// State of a pool repository, compared to what the pool recorded.
+pub struct RepositoryStatus {
+    /// Repository path, relative to the pool root.
+    pub path: PathBuf,
+    /// Branch recorded in the pool.
+    pub branch: String,
+    /// Whether the repository is missing from the pool root.
+    pub missing: bool,
+    /// Live state, `None` if the repository is missing or could not be read.
+    pub state: Option<RepositoryState>,
+    /// Reason the repository could not be read.
+    pub error: Option<String>,
+}
+
+impl RepositoryStatus {
+    fn read(pool: &Pool, repository: &Repository) -> Self {
+        let full_path = repository.full_path(&pool.root);
+        let missing = !full_path.is_dir();
+        let (state, error) = if missing {
+            (None, None)
+        } else {
+            match RepositoryState::read(&full_path) {
+                Ok(state) => (Some(state), None),
+                Err(err) => (None, Some(err.to_string())),
+            }
+        };
+
+        RepositoryStatus {
+            path: repository.path.join(&repository.name),
+            branch: String::from(repository.branch_name()),
+            missing,
+            state,
+            error,
+        }
+    }
+
+    /// Whether the repository is on its recorded branch, with nothing to commit or sync.
+    pub fn is_clean(&self) -> bool {
+        match &self.state {
+            Some(state) => {
+                state.head.as_deref() == Some(self.branch.as_str())
+                    && state.staged + state.unstaged + state.untracked == 0
+                    && state.ahead + state.behind == 0
+            }
+            None => false,
+        }
+    }
+
+    fn row(&self) -> Vec<String> {
+        let path = self.path.display().to_string();
+        let state = match &self.state {
+            Some(state) => state,
+            None => {
+                let head = if self.missing { "(missing)" } else { "(error)" };
+                let mut row = vec![path, String::from(head), self.branch.clone()];
+                row.resize(9, String::from("-"));
+                return row;
+            }
+        };
+
+        let head = match &state.head {
+            Some(head) => head.clone(),
+            None => String::from("(detached)"),
+        };
+        vec![
+            path,
+            head,
+            self.branch.clone(),
+            state.ahead.to_string(),
+            state.behind.to_string(),
+            state.staged.to_string(),
+            state.unstaged.to_string(),
+            state.untracked.to_string(),
+            state.stashes.to_string(),
+        ]
+    }
+}
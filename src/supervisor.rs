@@ -0,0 +1,142 @@
+use serde::Serialize;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay between two checks of a running process.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_HANDLER: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// Reason a running process was killed by gmux.
+pub enum KillReason {
+    /// The process ran longer than the allowed timeout.
+    Timeout,
+    /// gmux was interrupted while the process was running.
+    Interrupt,
+}
+
+/// Catch Ctrl-C instead of exiting right away.
+///
+/// Running processes are killed by their supervisor when the interruption
+/// is noticed, and no new process should be started afterwards,
+/// see `is_interrupted`.
+pub(crate) fn handle_interrupts() {
+    INTERRUPT_HANDLER.call_once(|| {
+        if let Err(err) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+            log::warn!("Could not set the interruption handler: {}", err);
+        }
+    });
+}
+
+pub(crate) fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Run a process and capture its output, like `Command::output`.
+///
+/// The process is killed when it runs longer than `timeout`, or when gmux
+/// is interrupted. It runs in its own process group, so the processes it
+/// spawned are killed along with it.
+pub(crate) fn output(
+    process: &mut Command,
+    timeout: Option<Duration>,
+) -> (io::Result<Output>, Option<KillReason>) {
+    process
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    isolate(process);
+
+    let mut child = match process.spawn() {
+        Ok(child) => child,
+        Err(err) => return (Err(err), None),
+    };
+
+    let stdout = read_to_end(child.stdout.take());
+    let stderr = read_to_end(child.stderr.take());
+
+    let start = Instant::now();
+    let mut killed = None;
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => (),
+            Err(err) => break Err(err),
+        }
+
+        if killed.is_none() {
+            if is_interrupted() {
+                killed = Some(KillReason::Interrupt);
+            } else if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                killed = Some(KillReason::Timeout);
+            }
+            if killed.is_some() {
+                kill(&mut child);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let output = status.and_then(|status| {
+        Ok(Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?,
+        })
+    });
+
+    (output, killed)
+}
+
+fn read_to_end<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> Option<thread::JoinHandle<io::Result<Vec<u8>>>> {
+    pipe.map(|mut pipe| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            pipe.read_to_end(&mut buffer)?;
+            Ok(buffer)
+        })
+    })
+}
+
+fn join(reader: Option<thread::JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+    match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| io::Error::other("Output reader panicked."))?,
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(unix)]
+fn isolate(process: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    process.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn isolate(_process: &mut Command) {}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // The process group shares the process id, see `isolate`.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
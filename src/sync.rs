@@ -0,0 +1,251 @@
+use super::{
+    FetchManager, HostThrottle, Pool, Remote, Repository, RepositoryState, Result, Selection,
+    Settings,
+};
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, ErrorCode, Oid, Signature};
+use rayon::prelude::*;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct SyncManager;
+
+impl SyncManager {
+    /// Fetch the upstream of the selected repositories and fast-forward
+    /// their current branch, or rebase it when `rebase` is set.
+    ///
+    /// `progress` is called as each repository completes, with the number
+    /// of completed repositories and the total.
+    pub fn sync(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        rebase: bool,
+        progress: &(dyn Fn(&RepositorySync, usize, usize) + Sync),
+    ) -> Result<Vec<RepositorySync>> {
+        let pool = Pool::from_current(settings)?;
+
+        let repositories: Vec<&Repository> = pool
+            .repositories
+            .iter()
+            .filter(|repository| selection.matches(&pool, repository))
+            .collect();
+
+        let (workers, throttle) = pool.workers(settings, jobs)?;
+        let completed = AtomicUsize::new(0);
+
+        let mut syncs: Vec<RepositorySync> = workers.install(|| {
+            repositories
+                .par_iter()
+                .map(|repository| {
+                    let action = SyncManager::sync_repository(
+                        settings, &pool, &throttle, repository, rebase,
+                    )
+                    .unwrap_or_else(|err| SyncAction::Failed(err.to_string()));
+                    let sync = RepositorySync {
+                        repository: repository.to_string(),
+                        action,
+                    };
+                    let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    progress(&sync, completed, repositories.len());
+                    sync
+                })
+                .collect()
+        });
+        syncs.sort_by(|a, b| a.repository.cmp(&b.repository));
+
+        Ok(syncs)
+    }
+
+    fn sync_repository(
+        settings: &Settings,
+        pool: &Pool,
+        throttle: &HostThrottle,
+        repository: &Repository,
+        rebase: bool,
+    ) -> Result<SyncAction> {
+        let path = repository.full_path(&pool.root);
+        if !path.is_dir() {
+            return Ok(SyncAction::Skipped(SkipReason::Missing));
+        }
+        let git = git2::Repository::open(path)?;
+
+        if git.head_detached()? {
+            return Ok(SyncAction::Skipped(SkipReason::Detached));
+        }
+        let head = git.head()?;
+        let (refname, name) = match (head.name(), head.shorthand()) {
+            (Some(refname), Some(name)) => (String::from(refname), String::from(name)),
+            _ => return Ok(SyncAction::Skipped(SkipReason::Detached)),
+        };
+
+        let upstream_remote = match git.branch_upstream_remote(&refname) {
+            Ok(remote) => remote,
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                return Ok(SyncAction::Skipped(SkipReason::NoUpstream))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let remote = {
+            let remote_name = upstream_remote
+                .as_str()
+                .expect("Could not parse upstream remote name.");
+            let remote = git.find_remote(remote_name)?;
+            Remote {
+                name: String::from(remote_name),
+                url: String::from(remote.url().unwrap_or_default()),
+            }
+        };
+
+        {
+            let _permit = throttle.acquire(remote.host());
+            FetchManager::fetch_remote(settings, &git, &remote.name, false)?;
+        }
+
+        let branch = git.find_branch(&name, BranchType::Local)?;
+        let local = branch
+            .get()
+            .target()
+            .expect("Local branches point to a commit.");
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            // The upstream branch may have been deleted from the remote.
+            Err(_) => return Ok(SyncAction::Skipped(SkipReason::NoUpstream)),
+        };
+        let upstream = upstream
+            .get()
+            .target()
+            .expect("Remote branches point to a commit.");
+
+        let (ahead, behind) = git.graph_ahead_behind(local, upstream)?;
+        if behind == 0 {
+            return Ok(SyncAction::UpToDate);
+        }
+        if RepositoryState::is_dirty(&git)? {
+            return Ok(SyncAction::Skipped(SkipReason::Dirty));
+        }
+
+        if ahead == 0 {
+            let target = git.find_commit(upstream)?;
+            git.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe()))?;
+            git.find_reference(&refname)?
+                .set_target(upstream, "gmux sync: fast-forward")?;
+            return Ok(SyncAction::FastForwarded { commits: behind });
+        }
+
+        if !rebase {
+            return Ok(SyncAction::Skipped(SkipReason::Diverged { ahead, behind }));
+        }
+        SyncManager::rebase(&git, upstream, behind)
+    }
+
+    /// Rebase the current branch on its upstream, aborting on the first conflict.
+    fn rebase(git: &git2::Repository, upstream: Oid, behind: usize) -> Result<SyncAction> {
+        let signature = git
+            .signature()
+            .or_else(|_| Signature::now("gmux", "gmux"))?;
+        let upstream = git.find_annotated_commit(upstream)?;
+        let mut rebase = git.rebase(None, Some(&upstream), None, None)?;
+
+        while let Some(operation) = rebase.next() {
+            let applied = operation.map_err(|err| err.into()).and_then(|_| {
+                if git.index()?.has_conflicts() {
+                    return Ok(false);
+                }
+                match rebase.commit(None, &signature, None) {
+                    Ok(_) => Ok(true),
+                    // The commit is already part of the upstream.
+                    Err(err) if err.code() == ErrorCode::Applied => Ok(true),
+                    Err(err) => Err(err.into()),
+                }
+            });
+
+            match applied {
+                Ok(true) => (),
+                Ok(false) => {
+                    rebase.abort()?;
+                    return Ok(SyncAction::Skipped(SkipReason::Conflict));
+                }
+                Err(err) => {
+                    rebase.abort()?;
+                    return Err(err);
+                }
+            }
+        }
+        rebase.finish(Some(&signature))?;
+
+        Ok(SyncAction::Rebased { commits: behind })
+    }
+}
+
+#[derive(Debug)]
+/// Result of synchronizing a repository.
+pub struct RepositorySync {
+    /// Repository path, relative to the pool root.
+    pub repository: String,
+    pub action: SyncAction,
+}
+
+#[derive(Debug, PartialEq)]
+/// What happened to a repository current branch.
+pub enum SyncAction {
+    /// Nothing to pull from the upstream.
+    UpToDate,
+    /// The branch was fast-forwarded to its upstream.
+    FastForwarded {
+        commits: usize,
+    },
+    /// The branch was rebased on its upstream.
+    Rebased {
+        commits: usize,
+    },
+    /// The branch was left untouched.
+    Skipped(SkipReason),
+    Failed(String),
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncAction::UpToDate => write!(f, "up to date"),
+            SyncAction::FastForwarded { commits } => {
+                write!(f, "fast-forwarded {} commits", commits)
+            }
+            SyncAction::Rebased { commits } => write!(f, "rebased on {} new commits", commits),
+            SyncAction::Skipped(reason) => write!(f, "skipped, {}", reason),
+            SyncAction::Failed(err) => write!(f, "failed, {}", err),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// Reason a repository was not synchronized.
+pub enum SkipReason {
+    Missing,
+    Dirty,
+    Detached,
+    NoUpstream,
+    Diverged {
+        ahead: usize,
+        behind: usize,
+    },
+    /// Rebasing conflicted, and was aborted.
+    Conflict,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Missing => write!(f, "the repository is missing"),
+            SkipReason::Dirty => write!(f, "uncommitted changes"),
+            SkipReason::Detached => write!(f, "HEAD is detached"),
+            SkipReason::NoUpstream => write!(f, "no upstream branch"),
+            SkipReason::Diverged { ahead, behind } => write!(
+                f,
+                "diverged from upstream ({} ahead, {} behind), use --rebase",
+                ahead, behind
+            ),
+            SkipReason::Conflict => write!(f, "rebase conflicts, it was aborted"),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+
+/// Limit the number of concurrent operations against a single remote host.
+///
+/// Workers call `acquire` before contacting a host, and block until
+/// a slot is available. The slot is released when the returned
+/// permit is dropped.
+pub struct HostThrottle {
+    limit: Option<usize>,
+    running: Mutex<HashMap<String, usize>>,
+    released: Condvar,
+}
+
+impl HostThrottle {
+    /// Create a throttle, `None` or `0` meaning no limit.
+    pub fn new(limit: Option<usize>) -> Self {
+        HostThrottle {
+            limit: limit.filter(|limit| *limit > 0),
+            running: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self, host: Option<String>) -> HostPermit<'_> {
+        let host = match (self.limit, host) {
+            (Some(limit), Some(host)) => {
+                let mut running = self
+                    .running
+                    .lock()
+                    .expect("Host throttle lock is poisoned.");
+                while running.get(&host).copied().unwrap_or(0) >= limit {
+                    running = self
+                        .released
+                        .wait(running)
+                        .expect("Host throttle lock is poisoned.");
+                }
+                *running.entry(host.clone()).or_insert(0) += 1;
+                Some(host)
+            }
+            _ => None,
+        };
+        HostPermit {
+            throttle: self,
+            host,
+        }
+    }
+
+    fn release(&self, host: &str) {
+        let mut running = self
+            .running
+            .lock()
+            .expect("Host throttle lock is poisoned.");
+        if let Some(count) = running.get_mut(host) {
+            *count -= 1;
+        }
+        self.released.notify_all();
+    }
+}
+
+/// A slot held on a remote host, released when dropped.
+pub struct HostPermit<'a> {
+    throttle: &'a HostThrottle,
+    host: Option<String>,
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(host) = &self.host {
+            self.throttle.release(host);
+        }
+    }
+}
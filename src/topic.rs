@@ -0,0 +1,368 @@
+use super::status::table;
+use super::{
+    BranchChange, BranchManager, BranchOutcome, Pool, Repository, Result, Selection, Settings,
+    REMOTE_BRANCH_PREFIX,
+};
+use git2::{BranchType, Oid};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct TopicManager;
+
+impl TopicManager {
+    /// Locate a topic branch in the selected repositories of the current pool.
+    pub fn topic(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        name: &str,
+    ) -> Result<Vec<RepositoryTopic>> {
+        let pool = Pool::from_current(settings)?;
+
+        let (workers, _) = pool.workers(settings, jobs)?;
+
+        let mut topics: Vec<RepositoryTopic> = workers.install(|| {
+            pool.repositories
+                .par_iter()
+                .filter(|repository| selection.matches(&pool, repository))
+                .map(|repository| RepositoryTopic::read(&pool, repository, name))
+                .collect()
+        });
+        topics.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(topics)
+    }
+
+    /// Delete a topic branch, locally and from the fetch remote, from the
+    /// selected repositories where it is merged in the default branch.
+    ///
+    /// The local and remote branches are checked separately, only the merged
+    /// ones are deleted. The fetch remote is fetched first, and its branch is
+    /// only deleted if it did not move since it was checked. Default and
+    /// recorded branches are never deleted.
+    pub fn cleanup(
+        settings: &Settings,
+        jobs: Option<usize>,
+        selection: &Selection,
+        name: &str,
+    ) -> Result<Vec<BranchChange>> {
+        BranchManager::run(settings, jobs, selection, |pool, repository, throttle| {
+            // Remote tracking branches may be stale, the remote branch is
+            // checked at its current tip.
+            let git = git2::Repository::open(repository.full_path(&pool.root))?;
+            BranchManager::fetch(settings, &git, repository, throttle)?;
+
+            let topic = RepositoryTopic::read(pool, repository, name);
+            if let Some(err) = topic.error {
+                return Ok(BranchOutcome::Failed(err));
+            }
+            if !topic.local && !topic.remote {
+                return Ok(BranchOutcome::Missing);
+            }
+            let default_branch = match topic.default_branch {
+                Some(default_branch) => default_branch,
+                None => {
+                    return Ok(BranchOutcome::Skipped(String::from(
+                        "no default branch to compare to",
+                    )))
+                }
+            };
+            let remote_prefix = format!("{}/", repository.fetch.name);
+            if name == repository.branch_name()
+                || default_branch.trim_start_matches(&remote_prefix) == name
+            {
+                return Ok(BranchOutcome::Skipped(String::from(
+                    "it is the default branch",
+                )));
+            }
+
+            let mut kept = Vec::new();
+            let mut failures = Vec::new();
+
+            let mut deleted = false;
+            if topic.local {
+                let mut branch = git.find_branch(name, BranchType::Local)?;
+                if !topic.local_merged {
+                    kept.push(format!(
+                        "the local branch is not merged in {}",
+                        default_branch
+                    ));
+                } else if branch.is_head() {
+                    kept.push(String::from("the local branch is the current one"));
+                } else {
+                    branch.delete()?;
+                    deleted = true;
+                }
+            }
+
+            let mut pushed = false;
+            if topic.remote {
+                let checked = git
+                    .find_branch(&format!("{}{}", remote_prefix, name), BranchType::Remote)?
+                    .get()
+                    .target();
+                if !topic.remote_merged {
+                    kept.push(format!(
+                        "the remote branch is not merged in {}",
+                        default_branch
+                    ));
+                } else {
+                    match BranchManager::remote_tip(settings, &git, repository, throttle, name) {
+                        Ok(tip) if tip.is_some() && tip == checked => {
+                            match BranchManager::delete_remote(
+                                settings, &git, repository, throttle, name,
+                            ) {
+                                Ok(()) => pushed = true,
+                                Err(err) => failures
+                                    .push(format!("could not delete the remote branch: {}", err)),
+                            }
+                        }
+                        Ok(_) => {
+                            kept.push(String::from("the remote branch moved since it was checked"))
+                        }
+                        Err(err) => {
+                            failures.push(format!("could not read the remote branch: {}", err))
+                        }
+                    }
+                }
+            }
+
+            Ok(if deleted || pushed {
+                kept.append(&mut failures);
+                if kept.is_empty() {
+                    BranchOutcome::Deleted { pushed }
+                } else {
+                    BranchOutcome::PartlyDeleted {
+                        pushed,
+                        reason: kept.join(", "),
+                    }
+                }
+            } else if !failures.is_empty() {
+                kept.append(&mut failures);
+                BranchOutcome::Failed(kept.join(", "))
+            } else {
+                BranchOutcome::Skipped(kept.join(", "))
+            })
+        })
+    }
+
+    /// Render topics as a table, one row per repository.
+    pub fn table(topics: &[RepositoryTopic]) -> String {
+        let header = [
+            "REPOSITORY",
+            "LOCAL",
+            "REMOTE",
+            "DEFAULT",
+            "AHEAD",
+            "BEHIND",
+            "MERGED",
+            "LAST COMMIT",
+        ];
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        let rows: Vec<(Vec<String>, bool)> = topics
+            .iter()
+            .map(|topic| (topic.row(now), topic.is_unpushed()))
+            .collect();
+        table(&header, &rows)
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// Presence and state of a topic branch in a pool repository.
+pub struct RepositoryTopic {
+    /// Repository path, relative to the pool root.
+    pub path: PathBuf,
+    /// Whether the branch exists locally.
+    pub local: bool,
+    /// Whether the branch exists on the fetch remote.
+    pub remote: bool,
+    /// Whether the local and remote branches point to different commits.
+    pub diverged: bool,
+    /// Branch the topic is compared to, the fetch remote HEAD
+    /// or the recorded branch.
+    pub default_branch: Option<String>,
+    /// Topic commits missing from the default branch.
+    pub ahead: usize,
+    /// Default branch commits missing from the topic.
+    pub behind: usize,
+    /// Whether every existing topic branch is part of the default branch.
+    pub merged: bool,
+    /// Whether the local branch is part of the default branch.
+    pub local_merged: bool,
+    /// Whether the remote branch is part of the default branch.
+    pub remote_merged: bool,
+    /// Unix time of the topic last commit.
+    pub last_commit: Option<i64>,
+    /// Reason the repository could not be read.
+    pub error: Option<String>,
+}
+
+impl RepositoryTopic {
+    fn read(pool: &Pool, repository: &Repository, name: &str) -> Self {
+        let mut topic = RepositoryTopic {
+            path: repository.path.join(&repository.name),
+            local: false,
+            remote: false,
+            diverged: false,
+            default_branch: None,
+            ahead: 0,
+            behind: 0,
+            merged: false,
+            local_merged: false,
+            remote_merged: false,
+            last_commit: None,
+            error: None,
+        };
+
+        let full_path = repository.full_path(&pool.root);
+        if !full_path.is_dir() {
+            topic.error = Some(String::from("the repository is missing"));
+            return topic;
+        }
+        if let Err(err) = topic.inspect(&full_path, repository, name) {
+            topic.error = Some(err.to_string());
+        }
+        topic
+    }
+
+    fn inspect(
+        &mut self,
+        path: &std::path::Path,
+        repository: &Repository,
+        name: &str,
+    ) -> Result<()> {
+        let git = git2::Repository::open(path)?;
+
+        let target = |branch: std::result::Result<git2::Branch, git2::Error>| {
+            branch.ok().and_then(|branch| branch.get().target())
+        };
+        let local = target(git.find_branch(name, BranchType::Local));
+        let remote = target(git.find_branch(
+            &format!("{}/{}", repository.fetch.name, name),
+            BranchType::Remote,
+        ));
+        self.local = local.is_some();
+        self.remote = remote.is_some();
+        self.diverged = matches!((local, remote), (Some(local), Some(remote)) if local != remote);
+
+        let tip = match local.or(remote) {
+            Some(tip) => tip,
+            None => return Ok(()),
+        };
+        self.last_commit = Some(git.find_commit(tip)?.time().seconds());
+
+        let (default_branch, default) = match RepositoryTopic::default_branch(&git, repository) {
+            Some(default) => default,
+            None => return Ok(()),
+        };
+        let (ahead, behind) = git.graph_ahead_behind(tip, default)?;
+        self.default_branch = Some(default_branch);
+        self.ahead = ahead;
+        self.behind = behind;
+
+        let merged =
+            |tip: Option<Oid>| tip.is_some_and(|tip| BranchManager::is_merged(&git, tip, default));
+        self.local_merged = merged(local);
+        self.remote_merged = merged(remote);
+        self.merged =
+            (local.is_none() || self.local_merged) && (remote.is_none() || self.remote_merged);
+
+        Ok(())
+    }
+
+    /// Default branch of the fetch remote, falling back to the recorded branch.
+    fn default_branch(git: &git2::Repository, repository: &Repository) -> Option<(String, Oid)> {
+        let remote_head = format!("{}{}/HEAD", REMOTE_BRANCH_PREFIX, repository.fetch.name);
+        if let Ok(reference) = git.find_reference(&remote_head) {
+            if let Ok(resolved) = reference.resolve() {
+                if let (Some(name), Some(oid)) = (resolved.shorthand(), resolved.target()) {
+                    return Some((String::from(name), oid));
+                }
+            }
+        }
+
+        let recorded = repository.branch_name();
+        [
+            (
+                format!("{}/{}", repository.fetch.name, recorded),
+                BranchType::Remote,
+            ),
+            (String::from(recorded), BranchType::Local),
+        ]
+        .iter()
+        .find_map(|(name, branch_type)| {
+            git.find_branch(name, *branch_type)
+                .ok()
+                .and_then(|branch| branch.get().target())
+                .map(|oid| (name.clone(), oid))
+        })
+    }
+
+    /// Whether the topic has local commits missing from the remote.
+    pub fn is_unpushed(&self) -> bool {
+        self.local && (!self.remote || self.diverged)
+    }
+
+    fn row(&self, now: i64) -> Vec<String> {
+        let presence = |present: bool| String::from(if present { "yes" } else { "-" });
+        let path = self.path.display().to_string();
+        if let Some(err) = &self.error {
+            let mut row = vec![path, format!("({})", err)];
+            row.resize(8, String::from("-"));
+            return row;
+        }
+
+        let remote = if self.diverged {
+            String::from("differs")
+        } else {
+            presence(self.remote)
+        };
+        let (ahead, behind, merged) = match self.default_branch {
+            Some(_) => (
+                self.ahead.to_string(),
+                self.behind.to_string(),
+                presence(self.merged),
+            ),
+            None => (String::from("-"), String::from("-"), String::from("-")),
+        };
+        vec![
+            path,
+            presence(self.local),
+            remote,
+            self.default_branch
+                .clone()
+                .unwrap_or_else(|| String::from("-")),
+            ahead,
+            behind,
+            merged,
+            self.last_commit
+                .map(|time| RepositoryTopic::age(now - time))
+                .unwrap_or_else(|| String::from("-")),
+        ]
+    }
+
+    /// Human readable age of a commit, from its number of seconds.
+    fn age(seconds: i64) -> String {
+        let units = [
+            (365 * 24 * 3600, "year"),
+            (30 * 24 * 3600, "month"),
+            (7 * 24 * 3600, "week"),
+            (24 * 3600, "day"),
+            (3600, "hour"),
+            (60, "minute"),
+        ];
+        for (length, unit) in units.iter() {
+            let count = seconds / length;
+            if count > 0 {
+                let plural = if count > 1 { "s" } else { "" };
+                return format!("{} {}{} ago", count, unit, plural);
+            }
+        }
+        String::from("just now")
+    }
+}